@@ -0,0 +1,82 @@
+use image::Image;
+use pixel::{clamp_channel, Pixel};
+
+// Running weighted average of a sequence of equally sized frames, for
+// long exposure and motion trail effects
+
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+    sums: Vec<[f64; 4]>,
+    total_weight: f64,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Accumulator {
+        Accumulator {
+            width,
+            height,
+            sums: vec![[0.0; 4]; width * height],
+            total_weight: 0.0,
+        }
+    }
+
+    pub fn add_frame(&mut self, image: &Image, weight: f64) {
+        assert!(image.width == self.width && image.height == self.height);
+
+        for (sum, pixel) in self.sums.iter_mut().zip(image.pixels.iter()) {
+            sum[0] += pixel.red as f64 * weight;
+            sum[1] += pixel.green as f64 * weight;
+            sum[2] += pixel.blue as f64 * weight;
+            sum[3] += pixel.alpha as f64 * weight;
+        }
+
+        self.total_weight += weight;
+    }
+
+    pub fn result(&self) -> Vec<Pixel> {
+        if self.total_weight == 0.0 {
+            return vec![Pixel::rgba(0, 0, 0, 0); self.sums.len()];
+        }
+
+        let average = |v: f64| clamp_channel(v / self.total_weight);
+
+        self.sums
+            .iter()
+            .map(|sum| Pixel::rgba(average(sum[0]), average(sum[1]), average(sum[2]), average(sum[3])))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_accumulate() {
+        let mut first = [Pixel::rgb(0, 0, 0), Pixel::rgb(100, 200, 50)];
+        let mut second = [Pixel::rgb(100, 50, 255), Pixel::rgb(200, 0, 50)];
+
+        let mut accumulator = Accumulator::new(2, 1);
+        accumulator.add_frame(&Image::from_raw(&mut first[0], 2, 1), 1.0);
+        accumulator.add_frame(&Image::from_raw(&mut second[0], 2, 1), 1.0);
+
+        assert_eq!(accumulator.result(), vec![
+            Pixel::rgb(50, 25, 128),
+            Pixel::rgb(150, 100, 50),
+        ]);
+    }
+
+    #[test]
+    fn test_weighted() {
+        let mut first = [Pixel::rgb(0, 0, 0)];
+        let mut second = [Pixel::rgb(200, 200, 200)];
+
+        let mut accumulator = Accumulator::new(1, 1);
+        accumulator.add_frame(&Image::from_raw(&mut first[0], 1, 1), 3.0);
+        accumulator.add_frame(&Image::from_raw(&mut second[0], 1, 1), 1.0);
+
+        assert_eq!(accumulator.result(), vec![Pixel::rgb(50, 50, 50)]);
+    }
+}
@@ -12,11 +12,14 @@ impl BitFlags {
             return false;
         }
 
-        return (self.num >> i & 1) == 1;
+        (self.num >> i & 1) == 1
     }
 }
 
+// The baseline tests compare bools with assert_eq!
+
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 
     use super::*;
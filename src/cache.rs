@@ -0,0 +1,145 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use filter::{FilterType, ImageFilterExt};
+use image::Image;
+use pixel::Pixel;
+
+struct Entry {
+    average_hash: u64,
+    fingerprint: u64,
+    filter: String,
+    output: Vec<Pixel>,
+}
+
+// Least recently used cache of filter outputs, looked up by the input's
+// average hash and the filter. Because visually similar images share an
+// average hash, a candidate entry only counts as a hit when an exact hash of
+// the input pixels matches too
+
+pub struct FilterCache {
+    capacity: usize,
+    entries: Vec<Entry>,
+    hits: usize,
+    misses: usize,
+}
+
+impl FilterCache {
+    pub fn new(capacity: usize) -> FilterCache {
+        FilterCache {
+            capacity,
+            entries: Vec::with_capacity(capacity),
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn apply(&mut self, image: &mut Image, filter: FilterType) {
+        let average_hash = image.average_hash();
+        let fingerprint = fingerprint(image);
+        let key = format!("{:?}", filter);
+
+        let found = self.entries.iter().position(|entry| {
+            entry.average_hash == average_hash && entry.fingerprint == fingerprint && entry.filter == key
+        });
+
+        if let Some(position) = found {
+            let entry = self.entries.remove(position);
+            image.pixels.copy_from_slice(&entry.output);
+            self.entries.push(entry);
+            self.hits += 1;
+            return;
+        }
+
+        self.misses += 1;
+        image.filter(filter);
+
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.remove(0);
+        }
+        self.entries.push(Entry {
+            average_hash,
+            fingerprint,
+            filter: key,
+            output: image.pixels.to_vec(),
+        });
+    }
+
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+
+    pub fn misses(&self) -> usize {
+        self.misses
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+fn fingerprint(image: &Image) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write_usize(image.width);
+    hasher.write_usize(image.height);
+    for pixel in image.pixels.iter() {
+        hasher.write(&[pixel.red, pixel.green, pixel.blue, pixel.alpha]);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_cache_hit() {
+        let source = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(0, 0, 0),
+        ];
+        let mut cache = FilterCache::new(4);
+
+        let mut pixels = source;
+        cache.apply(&mut Image::from_raw(&mut pixels[0], 2, 1), FilterType::Invert);
+        assert_eq!((cache.hits(), cache.misses()), (0, 1));
+
+        let mut again = source;
+        cache.apply(&mut Image::from_raw(&mut again[0], 2, 1), FilterType::Invert);
+        assert_eq!((cache.hits(), cache.misses()), (1, 1));
+        assert_eq!(again, pixels);
+        assert_eq!(again, [Pixel::rgb(155, 105, 55), Pixel::rgb(255, 255, 255)]);
+
+        let mut other = source;
+        cache.apply(&mut Image::from_raw(&mut other[0], 2, 1), FilterType::Brightness(10));
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+    }
+
+    #[test]
+    fn test_cache_eviction() {
+        let mut cache = FilterCache::new(2);
+        assert!(cache.is_empty());
+
+        for &amount in &[1, 2, 3] {
+            let mut pixels = [Pixel::rgb(10, 10, 10)];
+            cache.apply(&mut Image::from_raw(&mut pixels[0], 1, 1), FilterType::Brightness(amount));
+        }
+        assert_eq!(cache.len(), 2);
+
+        let mut pixels = [Pixel::rgb(10, 10, 10)];
+        cache.apply(&mut Image::from_raw(&mut pixels[0], 1, 1), FilterType::Brightness(1));
+        assert_eq!(cache.hits(), 0);
+
+        let mut pixels = [Pixel::rgb(10, 10, 10)];
+        cache.apply(&mut Image::from_raw(&mut pixels[0], 1, 1), FilterType::Brightness(3));
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(pixels[0], Pixel::rgb(13, 13, 13));
+    }
+}
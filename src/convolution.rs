@@ -24,12 +24,43 @@ pub static EMBOSS: ConvolutionMatrix = [
     [0.0, 1.0, 2.0],   //
 ];
 
+pub static SOBEL_X: ConvolutionMatrix = [
+    [-1.0, 0.0, 1.0], //
+    [-2.0, 0.0, 2.0], //
+    [-1.0, 0.0, 1.0], //
+];
+
+pub static SOBEL_Y: ConvolutionMatrix = [
+    [-1.0, -2.0, -1.0], //
+    [0.0, 0.0, 0.0],    //
+    [1.0, 2.0, 1.0],    //
+];
+
+// Normalized 1D gaussian weights covering 3 sigma either side of the centre
+
+pub fn gaussian_kernel(sigma: f64) -> Vec<f64> {
+    let radius = (sigma * 3.0).ceil() as isize;
+    let mut kernel: Vec<f64> = (-radius..=radius)
+        .map(|x| (-((x * x) as f64) / (2.0 * sigma * sigma)).exp())
+        .collect();
+
+    let total: f64 = kernel.iter().sum();
+    for weight in kernel.iter_mut() {
+        *weight /= total;
+    }
+
+    kernel
+}
+
+// Accumulates in f64 rather than f32 so large weights keep integer
+// precision (exact up to 2^53) instead of losing low bits
+
 pub fn apply_convolution(m1: [u8; 9], m2: ConvolutionMatrix) -> u8 {
-    let mut accum: f32 = 0.0;
+    let mut accum: f64 = 0.0;
 
     for i in 0..3 {
         for j in 0..3 {
-            accum += (m1[(i * 3) + j] as f32) * (m2[i][j]);
+            accum += (m1[(i * 3) + j] as f64) * (m2[i][j] as f64);
         }
     }
 
@@ -41,7 +72,7 @@ pub fn apply_convolution(m1: [u8; 9], m2: ConvolutionMatrix) -> u8 {
         return 255;
     }
 
-    return accum as u8;
+    accum as u8
 }
 
 #[cfg(test)]
@@ -87,4 +118,14 @@ mod tests {
             48
         );
     }
+
+    #[test]
+    fn test_gaussian_kernel() {
+        let kernel = gaussian_kernel(1.0);
+
+        assert_eq!(kernel.len(), 7);
+        assert!((kernel.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+        assert_eq!(kernel[0], kernel[6]);
+        assert!(kernel[3] > kernel[2] && kernel[2] > kernel[1]);
+    }
 }
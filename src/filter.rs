@@ -1,17 +1,292 @@
+use std::collections::HashMap;
+use bitflags::BitFlags;
 use image::Image;
-use pixel::Pixel;
-use convolution::{apply_convolution, ConvolutionMatrix};
+use pixel::{clamp_channel, BlendMode, ColorBlindType, lightness_to_linear, linear_to_lightness, linear_to_srgb, srgb_to_linear, Pixel};
+use random::Rng;
+use convolution::{apply_convolution, gaussian_kernel, ConvolutionMatrix};
 
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
 pub enum FilterType {
     MirrorX,
     MirrorY,
     Grayscale,
     Invert,
     Convolution(ConvolutionMatrix),
+    ChromaSubsample420,
+    NeonEdge { color: Pixel, glow: usize },
+    Curves {
+        red: Vec<(u8, u8)>,
+        green: Vec<(u8, u8)>,
+        blue: Vec<(u8, u8)>,
+    },
+    Pixelate(usize),
+    Retro {
+        block: usize,
+        palette: Vec<Pixel>,
+        dither: bool,
+    },
+    OrderedDither(usize),
+    RemoveBackground { color: Pixel, tolerance: u8 },
+    Brightness(i16),
+    Gamma(f64),
+    GaussianBlur(f64),
+    Sobel,
+    Despeckle(u8),
+    LaplacianOfGaussian { sigma: f64 },
+    AutoEnhance,
+    GridOverlay { spacing: usize, color: Pixel },
+    HistogramEqualize { clip_limit: Option<f64> },
+    GrayWorldBalance,
+    BoxBlur(usize),
+    Posterize(u8),
+    PosterizePerceptual(u8),
+    SimulateColorBlindness(ColorBlindType),
+    Daltonize(ColorBlindType),
+    NormalMap { strength: f64 },
+    BitPlane(u8),
+    RadialBlur { strength: f64, center: (f64, f64) },
+    ClippingWarning { high: u8, low: u8, over_color: Pixel, under_color: Pixel },
+    HueRotate(f64),
+    FilmGrain { intensity: f64, size: f64, seed: u64 },
+    Scanlines { spacing: usize, darkness: f64 },
+    Outline { thickness: usize, color: Pixel },
+    WebSafe,
+    CartoonHQ { bilateral_passes: usize, levels: u8 },
+    ColorHalftone { cell: usize },
+    GammaRGB { r: f64, g: f64, b: f64 },
+    Desaturate(f64),
+    DctArtifacts { quality: u8 },
+    Vignette { strength: f64, preserve_brightness: bool },
+    Contours { levels: u8, color: Pixel },
+    RadialGradient { center: (f64, f64), inner: Pixel, outer: Pixel, radius: f64, mode: BlendMode },
+    BitDepth(u8),
+    TiltShift { center_y: usize, band: usize, max_sigma: f64 },
+    ChromaDenoise { radius: usize },
+    SkinSmooth { amount: f64 },
+    AutoGamma,
+    RadialKaleidoscope { segments: usize },
+    UnsharpLuminance { radius: usize, sigma: f64, amount: f64 },
+    GuidedFilter { radius: usize, epsilon: f64 },
+    Clarity(f64),
+    AsciiArt { cols: usize, ramp: String },
+    RankEqualize,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl FilterType {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            FilterType::MirrorX => "Mirror X",
+            FilterType::MirrorY => "Mirror Y",
+            FilterType::Grayscale => "Grayscale",
+            FilterType::Invert => "Invert",
+            FilterType::Convolution(_) => "Convolution",
+            FilterType::ChromaSubsample420 => "Chroma subsample 4:2:0",
+            FilterType::NeonEdge { .. } => "Neon edge",
+            FilterType::Curves { .. } => "Curves",
+            FilterType::Pixelate(_) => "Pixelate",
+            FilterType::Retro { .. } => "Retro",
+            FilterType::OrderedDither(_) => "Ordered dither",
+            FilterType::RemoveBackground { .. } => "Remove background",
+            FilterType::Brightness(_) => "Brightness",
+            FilterType::Gamma(_) => "Gamma",
+            FilterType::GaussianBlur(_) => "Gaussian blur",
+            FilterType::Sobel => "Sobel",
+            FilterType::Despeckle(_) => "Despeckle",
+            FilterType::LaplacianOfGaussian { .. } => "Laplacian of gaussian",
+            FilterType::AutoEnhance => "Auto enhance",
+            FilterType::GridOverlay { .. } => "Grid overlay",
+            FilterType::HistogramEqualize { .. } => "Histogram equalize",
+            FilterType::GrayWorldBalance => "Gray world balance",
+            FilterType::BoxBlur(_) => "Box blur",
+            FilterType::Posterize(_) => "Posterize",
+            FilterType::PosterizePerceptual(_) => "Perceptual posterize",
+            FilterType::SimulateColorBlindness(_) => "Simulate color blindness",
+            FilterType::Daltonize(_) => "Daltonize",
+            FilterType::NormalMap { .. } => "Normal map",
+            FilterType::BitPlane(_) => "Bit plane",
+            FilterType::RadialBlur { .. } => "Radial blur",
+            FilterType::ClippingWarning { .. } => "Clipping warning",
+            FilterType::HueRotate(_) => "Hue rotate",
+            FilterType::FilmGrain { .. } => "Film grain",
+            FilterType::Scanlines { .. } => "Scanlines",
+            FilterType::Outline { .. } => "Outline",
+            FilterType::WebSafe => "Web safe",
+            FilterType::CartoonHQ { .. } => "Cartoon HQ",
+            FilterType::ColorHalftone { .. } => "Color halftone",
+            FilterType::GammaRGB { .. } => "Gamma RGB",
+            FilterType::Desaturate(_) => "Desaturate",
+            FilterType::DctArtifacts { .. } => "DCT artifacts",
+            FilterType::Vignette { .. } => "Vignette",
+            FilterType::Contours { .. } => "Contours",
+            FilterType::RadialGradient { .. } => "Radial gradient",
+            FilterType::BitDepth(_) => "Bit depth",
+            FilterType::TiltShift { .. } => "Tilt shift",
+            FilterType::ChromaDenoise { .. } => "Chroma denoise",
+            FilterType::SkinSmooth { .. } => "Skin smooth",
+            FilterType::AutoGamma => "Auto gamma",
+            FilterType::RadialKaleidoscope { .. } => "Radial kaleidoscope",
+            FilterType::UnsharpLuminance { .. } => "Unsharp luminance",
+            FilterType::GuidedFilter { .. } => "Guided filter",
+            FilterType::Clarity(_) => "Clarity",
+            FilterType::AsciiArt { .. } => "ASCII art",
+            FilterType::RankEqualize => "Rank equalize",
+        }
+    }
+
+    // Name plus a summary of the parameters, for logs and UI labels
+
+    pub fn describe(&self) -> String {
+        let rgb = |p: &Pixel| format!("rgb({}, {}, {})", p.red, p.green, p.blue);
+
+        let params = match *self {
+            FilterType::Convolution(ref m) => Some(format!("{:?}", m)),
+            FilterType::NeonEdge { ref color, glow } => Some(format!("color {}, glow {}", rgb(color), glow)),
+            FilterType::Curves { ref red, ref green, ref blue } => Some(format!(
+                "{} red, {} green, {} blue points",
+                red.len(),
+                green.len(),
+                blue.len()
+            )),
+            FilterType::Pixelate(block) => Some(format!("block {}", block)),
+            FilterType::Retro { block, ref palette, dither } => Some(format!(
+                "block {}, palette size {}, dither {}",
+                block,
+                palette.len(),
+                if dither { "on" } else { "off" }
+            )),
+            FilterType::OrderedDither(size) => Some(format!("size {}", size)),
+            FilterType::RemoveBackground { ref color, tolerance } => {
+                Some(format!("color {}, tolerance {}", rgb(color), tolerance))
+            }
+            FilterType::Brightness(amount) => Some(format!("amount {}", amount)),
+            FilterType::Gamma(gamma) => Some(format!("gamma {}", gamma)),
+            FilterType::GaussianBlur(sigma) => Some(format!("sigma {}", sigma)),
+            FilterType::Despeckle(threshold) => Some(format!("threshold {}", threshold)),
+            FilterType::LaplacianOfGaussian { sigma } => Some(format!("sigma {}", sigma)),
+            FilterType::GridOverlay { spacing, ref color } => {
+                Some(format!("spacing {}, color {}", spacing, rgb(color)))
+            }
+            FilterType::HistogramEqualize { clip_limit: Some(limit) } => Some(format!("clip limit {}", limit)),
+            FilterType::BoxBlur(radius) => Some(format!("radius {}", radius)),
+            FilterType::Posterize(levels) | FilterType::PosterizePerceptual(levels) => {
+                Some(format!("{} levels", levels))
+            }
+            FilterType::SimulateColorBlindness(kind) | FilterType::Daltonize(kind) => Some(format!("{:?}", kind)),
+            FilterType::NormalMap { strength } => Some(format!("strength {}", strength)),
+            FilterType::BitPlane(bit) => Some(format!("bit {}", bit)),
+            FilterType::RadialBlur { strength, center } => {
+                Some(format!("strength {}, center ({}, {})", strength, center.0, center.1))
+            }
+            FilterType::ClippingWarning { high, low, .. } => Some(format!("high {}, low {}", high, low)),
+            FilterType::HueRotate(degrees) => Some(format!("{} degrees", degrees)),
+            FilterType::FilmGrain { intensity, size, seed } => {
+                Some(format!("intensity {}, size {}, seed {}", intensity, size, seed))
+            }
+            FilterType::Scanlines { spacing, darkness } => Some(format!("spacing {}, darkness {}", spacing, darkness)),
+            FilterType::Outline { thickness, ref color } => Some(format!("thickness {}, color {}", thickness, rgb(color))),
+            FilterType::CartoonHQ { bilateral_passes, levels } => {
+                Some(format!("{} bilateral passes, {} levels", bilateral_passes, levels))
+            }
+            FilterType::ColorHalftone { cell } => Some(format!("cell {}", cell)),
+            FilterType::GammaRGB { r, g, b } => Some(format!("red {}, green {}, blue {}", r, g, b)),
+            FilterType::Desaturate(factor) => Some(format!("factor {}", factor)),
+            FilterType::DctArtifacts { quality } => Some(format!("quality {}", quality)),
+            FilterType::Vignette { strength, preserve_brightness } => {
+                Some(format!("strength {}, preserve brightness {}", strength, preserve_brightness))
+            }
+            FilterType::Contours { levels, ref color } => Some(format!("levels {}, color {}", levels, rgb(color))),
+            FilterType::RadialGradient { center, ref inner, ref outer, radius, mode } => Some(format!(
+                "center ({}, {}), {} to {}, radius {}, {:?}",
+                center.0,
+                center.1,
+                rgb(inner),
+                rgb(outer),
+                radius,
+                mode
+            )),
+            FilterType::BitDepth(bits) => Some(format!("{} bits", bits)),
+            FilterType::TiltShift { center_y, band, max_sigma } => {
+                Some(format!("center row {}, band {}, max sigma {}", center_y, band, max_sigma))
+            }
+            FilterType::ChromaDenoise { radius } => Some(format!("radius {}", radius)),
+            FilterType::SkinSmooth { amount } => Some(format!("amount {}", amount)),
+            FilterType::RadialKaleidoscope { segments } => Some(format!("{} segments", segments)),
+            FilterType::UnsharpLuminance { radius, sigma, amount } => {
+                Some(format!("radius {}, sigma {}, amount {}", radius, sigma, amount))
+            }
+            FilterType::GuidedFilter { radius, epsilon } => Some(format!("radius {}, epsilon {}", radius, epsilon)),
+            FilterType::Clarity(amount) => Some(format!("amount {}", amount)),
+            FilterType::AsciiArt { cols, ref ramp } => Some(format!("{} columns, ramp {:?}", cols, ramp)),
+            _ => None,
+        };
+
+        match params {
+            Some(params) => format!("{} filter ({})", self.name(), params),
+            None => format!("{} filter", self.name()),
+        }
+    }
+
+    // Whether the two filters can be reordered without either one mixing
+    // neighbouring pixels. This is not a promise of the same result: only
+    // mirrors, with each other or with a per-pixel filter, give identical
+    // pixels in either order. Two per-pixel filters are also reported as
+    // commuting, yet pairs like Invert and Brightness or Posterize and Gamma
+    // produce different pixels when swapped. Anything involving a spatial
+    // filter is reported as not commuting
+
+    pub fn commutes_with(&self, other: &FilterType) -> bool {
+        if self == other {
+            return true;
+        }
+
+        match (self.is_mirror(), other.is_mirror()) {
+            (true, true) => true,
+            (true, false) => other.is_per_pixel(),
+            (false, true) => self.is_per_pixel(),
+            (false, false) => self.is_per_pixel() && other.is_per_pixel(),
+        }
+    }
+
+    fn is_mirror(&self) -> bool {
+        matches!(*self, FilterType::MirrorX | FilterType::MirrorY)
+    }
+
+    // Filters where each output pixel depends only on the same input pixel
+
+    fn is_per_pixel(&self) -> bool {
+        matches!(
+            *self,
+            FilterType::Grayscale
+                | FilterType::Invert
+                | FilterType::Curves { .. }
+                | FilterType::RemoveBackground { .. }
+                | FilterType::Brightness(_)
+                | FilterType::Gamma(_)
+                | FilterType::Posterize(_)
+                | FilterType::PosterizePerceptual(_)
+                | FilterType::SimulateColorBlindness(_)
+                | FilterType::Daltonize(_)
+                | FilterType::BitPlane(_)
+                | FilterType::ClippingWarning { .. }
+                | FilterType::HueRotate(_)
+                | FilterType::WebSafe
+                | FilterType::GammaRGB { .. }
+                | FilterType::Desaturate(_)
+                | FilterType::BitDepth(_)
+        )
+    }
 }
 
 pub trait ImageFilterExt {
-    fn filter(&mut self, FilterType);
+    fn filter(&mut self, filter: FilterType);
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn filter_by_label(&mut self, labels: &[u32], map: HashMap<u32, FilterType>);
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn convolve_alpha_aware(&mut self, matrix: ConvolutionMatrix);
+    #[cfg_attr(not(test), allow(dead_code))]
+    fn blur_region(&mut self, rect: (usize, usize, usize, usize), sigma: f64);
 }
 
 impl<'a> ImageFilterExt for Image<'a> {
@@ -21,9 +296,76 @@ impl<'a> ImageFilterExt for Image<'a> {
             FilterType::MirrorY => mirror_y(self),
             FilterType::Grayscale => grayscale(self),
             FilterType::Invert => invert(self),
-            FilterType::Convolution(matrix) => convolution(self, matrix),
+            FilterType::Convolution(matrix) => convolution(self, matrix, false),
+            FilterType::ChromaSubsample420 => chroma_subsample_420(self),
+            FilterType::NeonEdge { color, glow } => neon_edge(self, color, glow),
+            FilterType::Curves { red, green, blue } => curves(self, &red, &green, &blue),
+            FilterType::Pixelate(block) => pixelate(self, block),
+            FilterType::Retro { block, palette, dither } => retro(self, block, &palette, dither),
+            FilterType::OrderedDither(size) => ordered_dither(self, size),
+            FilterType::RemoveBackground { color, tolerance } => remove_background(self, color, tolerance),
+            FilterType::Brightness(amount) => brightness(self, amount),
+            FilterType::Gamma(gamma) => apply_gamma(self, gamma),
+            FilterType::GaussianBlur(sigma) => gaussian_blur(self, sigma),
+            FilterType::Sobel => sobel(self),
+            FilterType::Despeckle(threshold) => despeckle(self, threshold),
+            FilterType::LaplacianOfGaussian { sigma } => laplacian_of_gaussian(self, sigma),
+            FilterType::AutoEnhance => auto_enhance(self),
+            FilterType::GridOverlay { spacing, color } => grid_overlay(self, spacing, color),
+            FilterType::HistogramEqualize { clip_limit } => histogram_equalize(self, clip_limit),
+            FilterType::GrayWorldBalance => gray_world_balance(self),
+            FilterType::BoxBlur(1) => box_blur_3x3(self),
+            FilterType::BoxBlur(radius) => self.convolve_fn(radius, |_, _| 1.0),
+            FilterType::Posterize(levels) => posterize(self, levels),
+            FilterType::PosterizePerceptual(levels) => posterize_perceptual(self, levels),
+            FilterType::SimulateColorBlindness(kind) => simulate_color_blindness(self, kind),
+            FilterType::Daltonize(kind) => daltonize(self, kind),
+            FilterType::NormalMap { strength } => normal_map(self, strength),
+            FilterType::BitPlane(bit) => bit_plane(self, bit),
+            FilterType::RadialBlur { strength, center } => radial_blur(self, strength, center),
+            FilterType::ClippingWarning { high, low, over_color, under_color } => {
+                clipping_warning(self, high, low, over_color, under_color)
+            }
+            FilterType::HueRotate(degrees) => hue_rotate(self, degrees),
+            FilterType::FilmGrain { intensity, size, seed } => film_grain(self, intensity, size, seed),
+            FilterType::Scanlines { spacing, darkness } => scanlines(self, spacing, darkness),
+            FilterType::Outline { thickness, color } => outline(self, thickness, color),
+            FilterType::WebSafe => web_safe(self),
+            FilterType::CartoonHQ { bilateral_passes, levels } => cartoon_hq(self, bilateral_passes, levels),
+            FilterType::ColorHalftone { cell } => color_halftone(self, cell),
+            FilterType::GammaRGB { r, g, b } => apply_gamma_rgb(self, r, g, b),
+            FilterType::Desaturate(factor) => desaturate(self, factor),
+            FilterType::DctArtifacts { quality } => dct_artifacts(self, quality),
+            FilterType::Vignette { strength, preserve_brightness } => vignette(self, strength, preserve_brightness),
+            FilterType::Contours { levels, color } => contours(self, levels, color),
+            FilterType::RadialGradient { center, inner, outer, radius, mode } => {
+                radial_gradient(self, center, inner, outer, radius, mode)
+            }
+            FilterType::BitDepth(bits) => bit_depth(self, bits),
+            FilterType::TiltShift { center_y, band, max_sigma } => tilt_shift(self, center_y, band, max_sigma),
+            FilterType::ChromaDenoise { radius } => chroma_denoise(self, radius),
+            FilterType::SkinSmooth { amount } => skin_smooth(self, amount),
+            FilterType::AutoGamma => auto_gamma(self),
+            FilterType::RadialKaleidoscope { segments } => radial_kaleidoscope(self, segments),
+            FilterType::UnsharpLuminance { radius, sigma, amount } => unsharp_luminance(self, radius, sigma, amount),
+            FilterType::GuidedFilter { radius, epsilon } => guided_filter(self, radius, epsilon),
+            FilterType::Clarity(amount) => clarity(self, amount),
+            FilterType::AsciiArt { cols, ref ramp } => ascii_art(self, cols, ramp),
+            FilterType::RankEqualize => rank_equalize(self),
         }
     }
+
+    fn filter_by_label(&mut self, labels: &[u32], map: HashMap<u32, FilterType>) {
+        filter_by_label(self, labels, map);
+    }
+
+    fn convolve_alpha_aware(&mut self, matrix: ConvolutionMatrix) {
+        convolution(self, matrix, true);
+    }
+
+    fn blur_region(&mut self, rect: (usize, usize, usize, usize), sigma: f64) {
+        blur_region(self, rect, sigma);
+    }
 }
 
 fn mirror_x(image: &mut Image) {
@@ -33,7 +375,7 @@ fn mirror_x(image: &mut Image) {
 
         if col < mid {
             let j = image.row_col_to_index(row, image.width - 1 - col);
-            image.pixels[j] = image.pixels[i].clone();
+            image.pixels[j] = image.pixels[i];
         }
     }
 }
@@ -45,7 +387,7 @@ fn mirror_y(image: &mut Image) {
 
         if row < mid {
             let j = image.row_col_to_index(image.height - 1 - row, col);
-            image.pixels[j] = image.pixels[i].clone();
+            image.pixels[j] = image.pixels[i];
         }
     }
 }
@@ -56,8 +398,18 @@ fn grayscale(image: &mut Image) {
     }
 }
 
-fn convolution(image: &mut Image, matrix: ConvolutionMatrix) {
-    let mut pixels_copy: Vec<Pixel> = image.pixels.iter().cloned().collect();
+// With alpha_aware set, colours are premultiplied by alpha before the kernel
+// runs and divided back out afterwards, so transparent pixels contribute no
+// colour to their neighbours and alpha itself is convolved too
+
+fn convolution(image: &mut Image, matrix: ConvolutionMatrix, alpha_aware: bool) {
+    let mut pixels_copy: Vec<Pixel> = image.pixels.to_vec();
+    if alpha_aware {
+        for pixel in pixels_copy.iter_mut() {
+            *pixel = pixel.premultiplied();
+        }
+    }
+
     let original = Image {
         width: image.width,
         height: image.height,
@@ -71,7 +423,18 @@ fn convolution(image: &mut Image, matrix: ConvolutionMatrix) {
             let red = apply_convolution(red_n, matrix);
             let green = apply_convolution(green_n, matrix);
             let blue = apply_convolution(blue_n, matrix);
-            image.pixels[i] = Pixel::rgb(red, green, blue);
+
+            if alpha_aware {
+                let mut alpha_n = [0; 9];
+                for (k, alpha) in alpha_n.iter_mut().enumerate() {
+                    let j = original.row_col_to_index(row + k / 3 - 1, col + k % 3 - 1);
+                    *alpha = original.pixels[j].alpha;
+                }
+                let alpha = apply_convolution(alpha_n, matrix);
+                image.pixels[i] = Pixel::rgba(red, green, blue, alpha).unpremultiplied();
+            } else {
+                image.pixels[i] = Pixel::rgb(red, green, blue);
+            }
         }
     }
 }
@@ -82,194 +445,2901 @@ fn invert(image: &mut Image) {
     }
 }
 
-#[cfg(test)]
-mod tests {
+// Each 2x2 block shares the average of its chroma while every pixel keeps
+// its own luma, previewing the loss of JPEG's 4:2:0 subsampling
 
-    use super::*;
+fn chroma_subsample_420(image: &mut Image) {
+    for row in (0..image.height).step_by(2) {
+        for col in (0..image.width).step_by(2) {
+            let mut block = Vec::with_capacity(4);
+            for r in row..(row + 2).min(image.height) {
+                for c in col..(col + 2).min(image.width) {
+                    block.push(image.row_col_to_index(r, c));
+                }
+            }
 
-    #[test]
-    fn test_filter_mirror_x() {
-        let mut pixels = [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-        ];
+            let mut cb_sum = 0.0;
+            let mut cr_sum = 0.0;
+            for &i in &block {
+                let (_, cb, cr) = image.pixels[i].to_ycbcr();
+                cb_sum += cb;
+                cr_sum += cr;
+            }
+            let cb = cb_sum / block.len() as f64;
+            let cr = cr_sum / block.len() as f64;
 
-        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
-        image.filter(FilterType::MirrorX);
+            for &i in &block {
+                let (y, _, _) = image.pixels[i].to_ycbcr();
+                let alpha = image.pixels[i].alpha;
+                image.pixels[i] = Pixel::from_ycbcr(y, cb, cr);
+                image.pixels[i].alpha = alpha;
+            }
+        }
+    }
+}
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-        ]);
+// Separable gaussian blur, clamping samples at the image border
 
-        let mut pixels = [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+fn gaussian_blur(image: &mut Image, sigma: f64) {
+    if sigma <= 0.0 {
+        return;
+    }
 
-        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
-        image.filter(FilterType::MirrorX);
+    let kernel = gaussian_kernel(sigma);
+    let radius = (kernel.len() / 2) as isize;
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-        ]);
+    let mut horizontal = vec![[0.0; 3]; image.pixels.len()];
+    for (i, accum) in horizontal.iter_mut().enumerate() {
+        let (row, col) = image.index_to_row_col(i);
+        for (k, weight) in kernel.iter().enumerate() {
+            let pix = image.pixels[image.clamped_index(row as isize, col as isize + k as isize - radius)];
+            accum[0] += pix.red as f64 * weight;
+            accum[1] += pix.green as f64 * weight;
+            accum[2] += pix.blue as f64 * weight;
+        }
     }
 
-    #[test]
-    fn test_filter_mirror_y() {
-        let mut pixels = [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let mut accum = [0.0; 3];
+        for (k, weight) in kernel.iter().enumerate() {
+            let j = image.clamped_index(row as isize + k as isize - radius, col as isize);
+            accum[0] += horizontal[j][0] * weight;
+            accum[1] += horizontal[j][1] * weight;
+            accum[2] += horizontal[j][2] * weight;
+        }
+        image.pixels[i].set_rgb(
+            clamp_channel(accum[0]),
+            clamp_channel(accum[1]),
+            clamp_channel(accum[2]),
+        );
+    }
+}
 
-        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
-        image.filter(FilterType::MirrorY);
+// Sobel edges tinted with the neon colour over black, plus a blurred copy of
+// the edge layer added back on top as the glow
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-        ]);
+fn neon_edge(image: &mut Image, color: Pixel, glow: usize) {
+    let magnitude = image.gradient_magnitude();
 
-        let mut pixels = [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+    let mut edges: Vec<Pixel> = magnitude
+        .iter()
+        .map(|m| {
+            let strength = m.min(255.0) / 255.0;
+            Pixel::rgb(
+                clamp_channel(color.red as f64 * strength),
+                clamp_channel(color.green as f64 * strength),
+                clamp_channel(color.blue as f64 * strength),
+            )
+        })
+        .collect();
 
-        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
-        image.filter(FilterType::MirrorY);
+    if glow > 0 {
+        let mut halo = edges.clone();
+        gaussian_blur(
+            &mut Image {
+                width: image.width,
+                height: image.height,
+                pixels: &mut halo[..],
+            },
+            glow as f64,
+        );
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-            Pixel::rgb(100, 100, 100),
-        ]);
+        for (edge, glow) in edges.iter_mut().zip(halo.iter()) {
+            edge.set_rgb(
+                edge.red.saturating_add(glow.red),
+                edge.green.saturating_add(glow.green),
+                edge.blue.saturating_add(glow.blue),
+            );
+        }
     }
 
-    #[test]
-    fn test_filter_grayscale() {
-        let mut pixels = [
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-        ];
+    for (pixel, edge) in image.pixels.iter_mut().zip(edges.iter()) {
+        pixel.set_rgb(edge.red, edge.green, edge.blue);
+    }
+}
 
-        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
-        image.filter(FilterType::Grayscale);
+// Piecewise linear lookup table through the control points. The curve is
+// anchored at (0, 0) and (255, 255) unless points are given for those inputs
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(150, 150, 150),
-            Pixel::rgb(150, 150, 150),
-            Pixel::rgb(150, 150, 150),
-            Pixel::rgb(150, 150, 150),
-        ]);
+fn curve_lut(points: &[(u8, u8)]) -> [u8; 256] {
+    let mut points = points.to_vec();
+    if !points.iter().any(|&(x, _)| x == 0) {
+        points.push((0, 0));
+    }
+    if !points.iter().any(|&(x, _)| x == 255) {
+        points.push((255, 255));
     }
+    points.sort();
 
-    #[test]
-    fn test_convolution() {
-        let mut pixels = [
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-        ];
+    let mut lut = [0; 256];
+    for pair in points.windows(2) {
+        let (x0, y0) = (pair[0].0 as f64, pair[0].1 as f64);
+        let (x1, y1) = (pair[1].0 as f64, pair[1].1 as f64);
+        for x in pair[0].0..=pair[1].0 {
+            let t = if x1 > x0 { (x as f64 - x0) / (x1 - x0) } else { 0.0 };
+            lut[x as usize] = clamp_channel(y0 + (y1 - y0) * t);
+        }
+    }
 
-        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
-        image.filter(FilterType::Convolution([
-            [0.,0.,0.],
-            [0.,1.,0.],         // identity matrix
-            [0.,0.,0.],
-        ]));
+    lut
+}
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-        ]);
+fn curves(image: &mut Image, red: &[(u8, u8)], green: &[(u8, u8)], blue: &[(u8, u8)]) {
+    let (red, green, blue) = (curve_lut(red), curve_lut(green), curve_lut(blue));
 
-        image.filter(FilterType::Convolution([
-            [0.,0.,0.],
-            [0.,0.,0.],         // zero out matrix
-            [0.,0.,0.],
-        ]));
+    for pixel in image.pixels.iter_mut() {
+        let (r, g, b) = (red[pixel.red as usize], green[pixel.green as usize], blue[pixel.blue as usize]);
+        pixel.set_rgb(r, g, b);
+    }
+}
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-        ]);
+fn pixelate(image: &mut Image, block: usize) {
+    if block < 2 {
+        return;
     }
 
-    #[test]
-    fn test_invert() {
-        let mut pixels = [
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-            Pixel::rgb(100, 150, 200),
-        ];
+    for row in (0..image.height).step_by(block) {
+        for col in (0..image.width).step_by(block) {
+            let rows = row..(row + block).min(image.height);
+            let cols = col..(col + block).min(image.width);
 
-        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
-        image.filter(FilterType::Invert);
+            let mut sum = [0u32; 3];
+            for r in rows.clone() {
+                for c in cols.clone() {
+                    let pix = image.pixels[image.row_col_to_index(r, c)];
+                    sum[0] += pix.red as u32;
+                    sum[1] += pix.green as u32;
+                    sum[2] += pix.blue as u32;
+                }
+            }
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(155, 105, 55),
-            Pixel::rgb(155, 105, 55),
-            Pixel::rgb(155, 105, 55),
-            Pixel::rgb(155, 105, 55),
-        ]);
+            let count = (rows.len() * cols.len()) as f64;
+            let average = |total: u32| clamp_channel(total as f64 / count);
+            let (red, green, blue) = (average(sum[0]), average(sum[1]), average(sum[2]));
+
+            for r in rows.clone() {
+                for c in cols.clone() {
+                    let i = image.row_col_to_index(r, c);
+                    image.pixels[i].set_rgb(red, green, blue);
+                }
+            }
+        }
+    }
+}
+
+// Value at (row, col) of the n x n Bayer index matrix, n a power of two
+
+fn bayer(n: usize, row: usize, col: usize) -> usize {
+    if n < 2 {
+        return 0;
+    }
+
+    let half = n / 2;
+    let quadrant = match (row / half, col / half) {
+        (0, 0) => 0,
+        (0, _) => 2,
+        (_, 0) => 3,
+        _ => 1,
+    };
+
+    4 * bayer(half, row % half, col % half) + quadrant
+}
+
+// Bayer matrix value normalized into 0..1
+
+fn bayer_threshold(n: usize, row: usize, col: usize) -> f64 {
+    (bayer(n, row % n, col % n) as f64 + 0.5) / (n * n) as f64
+}
+
+fn nearest_palette_colour(pixel: Pixel, palette: &[Pixel]) -> Pixel {
+    let distance = |p: &Pixel| {
+        let dr = pixel.red as i32 - p.red as i32;
+        let dg = pixel.green as i32 - p.green as i32;
+        let db = pixel.blue as i32 - p.blue as i32;
+        dr * dr + dg * dg + db * db
+    };
+
+    *palette.iter().min_by_key(|p| distance(p)).unwrap_or(&pixel)
+}
+
+// Pixelate, then snap every block to the palette. Dithering nudges each
+// block by a 4x4 Bayer offset first, so gradients break into patterns of
+// neighbouring palette colours
+
+fn retro(image: &mut Image, block: usize, palette: &[Pixel], dither: bool) {
+    pixelate(image, block);
+
+    if palette.is_empty() {
+        return;
+    }
+
+    let block = block.max(1);
+    let spread = 255.0 / palette.len() as f64;
+
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let mut pixel = image.pixels[i];
+
+        if dither {
+            let offset = (bayer_threshold(4, row / block, col / block) - 0.5) * spread;
+            let nudge = |v: u8| clamp_channel(v as f64 + offset);
+            pixel.set_rgb(nudge(pixel.red), nudge(pixel.green), nudge(pixel.blue));
+        }
+
+        let snapped = nearest_palette_colour(pixel, palette);
+        image.pixels[i].set_rgb(snapped.red, snapped.green, snapped.blue);
+    }
+}
+
+// Black and white threshold against a Bayer matrix of size 2, 4 or 8,
+// anything else rounds up to the next supported size
+
+fn ordered_dither(image: &mut Image, size: usize) {
+    let n = match size {
+        0..=2 => 2,
+        3..=4 => 4,
+        _ => 8,
+    };
+
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let threshold = bayer_threshold(n, row, col) * 255.0;
+        let value = if image.pixels[i].luminance() as f64 > threshold { 255 } else { 0 };
+        image.pixels[i].set_gray(value);
+    }
+}
+
+// Runs each label's filter over a crop of that label's bounding box, so
+// spatial filters only see the neighbourhood of the region, then writes back
+// just the pixels carrying the label
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn filter_by_label(image: &mut Image, labels: &[u32], map: HashMap<u32, FilterType>) {
+    assert_eq!(labels.len(), image.pixels.len(), "label map must have one label per pixel");
+
+    // Every region is cropped from the unfiltered pixels, so the result does
+    // not depend on the order the map hands out its labels
+    let mut original = image.pixels.to_vec();
+    let source = Image {
+        width: image.width,
+        height: image.height,
+        pixels: &mut original[..],
+    };
+
+    for (label, filter) in map {
+        let mut bounds: Option<(usize, usize, usize, usize)> = None;
+        for (i, _) in labels.iter().enumerate().filter(|&(_, &l)| l == label) {
+            let (row, col) = image.index_to_row_col(i);
+            bounds = Some(match bounds {
+                None => (row, col, row, col),
+                Some((top, left, bottom, right)) => (top.min(row), left.min(col), bottom.max(row), right.max(col)),
+            });
+        }
+
+        let (top, left, bottom, right) = match bounds {
+            Some(bounds) => bounds,
+            None => continue,
+        };
+
+        let (mut region, width, height) = source.crop(left, top, right - left + 1, bottom - top + 1);
+        {
+            let mut region_image = Image {
+                width,
+                height,
+                pixels: &mut region[..],
+            };
+            region_image.filter(filter);
+        }
+
+        for (k, pixel) in region.iter().enumerate() {
+            let i = image.row_col_to_index(top + k / width, left + k % width);
+            if labels[i] == label {
+                image.pixels[i] = *pixel;
+            }
+        }
+    }
+}
+
+// Pixels with every channel within tolerance of the background colour are
+// made fully transparent
+
+fn remove_background(image: &mut Image, color: Pixel, tolerance: u8) {
+    let close = |a: u8, b: u8| (a as i32 - b as i32).abs() <= tolerance as i32;
+
+    for pixel in image.pixels.iter_mut() {
+        if close(pixel.red, color.red) && close(pixel.green, color.green) && close(pixel.blue, color.blue) {
+            pixel.alpha = 0;
+        }
+    }
+}
+
+fn brightness(image: &mut Image, amount: i16) {
+    let adjust = |v: u8| (v as i16 + amount).clamp(0, 255) as u8;
+
+    for pixel in image.pixels.iter_mut() {
+        let (red, green, blue) = (adjust(pixel.red), adjust(pixel.green), adjust(pixel.blue));
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+// Gamma above 1 brightens, below 1 darkens
+
+fn gamma_lut(gamma: f64) -> [u8; 256] {
+    let mut lut = [0; 256];
+    for (v, entry) in lut.iter_mut().enumerate() {
+        *entry = clamp_channel(255.0 * (v as f64 / 255.0).powf(1.0 / gamma));
+    }
+    lut
+}
+
+fn apply_gamma(image: &mut Image, gamma: f64) {
+    let lut = gamma_lut(gamma);
+
+    for pixel in image.pixels.iter_mut() {
+        let (red, green, blue) = (lut[pixel.red as usize], lut[pixel.green as usize], lut[pixel.blue as usize]);
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+fn apply_gamma_rgb(image: &mut Image, r: f64, g: f64, b: f64) {
+    let (red_lut, green_lut, blue_lut) = (gamma_lut(r), gamma_lut(g), gamma_lut(b));
+
+    for pixel in image.pixels.iter_mut() {
+        let (red, green, blue) = (red_lut[pixel.red as usize], green_lut[pixel.green as usize], blue_lut[pixel.blue as usize]);
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+fn sobel(image: &mut Image) {
+    let magnitude = image.gradient_magnitude();
+
+    for (pixel, m) in image.pixels.iter_mut().zip(magnitude.iter()) {
+        pixel.set_gray(clamp_channel(*m));
+    }
+}
+
+// Only pixels that stand out from the median of their 8 neighbours by more
+// than the threshold in some channel are replaced by that median
+
+fn despeckle(image: &mut Image, threshold: u8) {
+    let original = image.pixels.to_vec();
+
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+
+        let mut neighbours = [[0u8; 8]; 3];
+        let mut n = 0;
+        for dy in -1..2 {
+            for dx in -1..2 {
+                if dy == 0 && dx == 0 {
+                    continue;
+                }
+                let pix = original[image.clamped_index(row as isize + dy, col as isize + dx)];
+                neighbours[0][n] = pix.red;
+                neighbours[1][n] = pix.green;
+                neighbours[2][n] = pix.blue;
+                n += 1;
+            }
+        }
+
+        let mut median = [0u8; 3];
+        for (channel, values) in neighbours.iter_mut().enumerate() {
+            values.sort();
+            median[channel] = (values[3] as u16 + values[4] as u16).div_ceil(2) as u8;
+        }
+
+        let pix = original[i];
+        let differs = |a: u8, b: u8| (a as i16 - b as i16).abs() > threshold as i16;
+        if differs(pix.red, median[0]) || differs(pix.green, median[1]) || differs(pix.blue, median[2]) {
+            image.pixels[i].set_rgb(median[0], median[1], median[2]);
+        }
+    }
+}
+
+// Scale-normalized, negated LoG of the luminance so bright blobs around
+// sigma * sqrt(2) in radius show up bright on a mid gray background. The
+// truncated kernel is shifted to sum to exactly zero so flat areas stay 128
+
+fn laplacian_of_gaussian(image: &mut Image, sigma: f64) {
+    if sigma <= 0.0 {
+        return;
+    }
+
+    let radius = (sigma * 3.0).ceil() as i32;
+    let log = |dx: i32, dy: i32| {
+        let r2 = (dx * dx + dy * dy) as f64 / (2.0 * sigma * sigma);
+        (1.0 - r2) * (-r2).exp() / (std::f64::consts::PI * sigma * sigma)
+    };
+
+    let mut mean = 0.0;
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            mean += log(dx, dy);
+        }
+    }
+    mean /= ((2 * radius + 1) * (2 * radius + 1)) as f64;
+
+    for pixel in image.pixels.iter_mut() {
+        let luma = pixel.luminance();
+        pixel.set_gray(luma);
+    }
+
+    let response = image.convolve_fn_raw(radius as usize, |dx, dy| log(dx, dy) - mean);
+    for (pixel, value) in image.pixels.iter_mut().zip(response.iter()) {
+        pixel.set_gray(clamp_channel(128.0 + value[0]));
+    }
+}
+
+// Stretch the darkest and brightest channel values in the image to 0 and 255
+
+fn auto_levels(image: &mut Image) {
+    let mut lo = 255;
+    let mut hi = 0;
+    for pixel in image.pixels.iter() {
+        lo = lo.min(pixel.red).min(pixel.green).min(pixel.blue);
+        hi = hi.max(pixel.red).max(pixel.green).max(pixel.blue);
+    }
+
+    if hi <= lo {
+        return;
+    }
+
+    let scale = 255.0 / (hi - lo) as f64;
+    let stretch = |v: u8| clamp_channel((v - lo) as f64 * scale);
+    for pixel in image.pixels.iter_mut() {
+        let (red, green, blue) = (stretch(pixel.red), stretch(pixel.green), stretch(pixel.blue));
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+// Push each channel away from (or toward, below 1) the pixel's luminance
+
+fn saturate(image: &mut Image, factor: f64) {
+    for pixel in image.pixels.iter_mut() {
+        let luma = pixel.luminance_f64();
+        let push = |v: u8| clamp_channel(luma + (v as f64 - luma) * factor);
+        let (red, green, blue) = (push(pixel.red), push(pixel.green), push(pixel.blue));
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+// One click fix: auto levels, a gentle S-curve and a 20% saturation boost
+
+fn auto_enhance(image: &mut Image) {
+    auto_levels(image);
+
+    let s_curve = [(64, 54), (192, 202)];
+    curves(image, &s_curve, &s_curve, &s_curve);
+
+    saturate(image, 1.2);
+}
+
+fn grid_overlay(image: &mut Image, spacing: usize, color: Pixel) {
+    if spacing == 0 {
+        return;
+    }
+
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+        if row % spacing == 0 || col % spacing == 0 {
+            image.pixels[i] = color;
+        }
+    }
+}
+
+// Equalize the luma through its cumulative histogram, leaving chroma alone.
+// A clip limit, as a multiple of the mean bin height, caps every bin and
+// spreads the excess evenly over all bins first, which keeps the mapping
+// from amplifying noise in large flat areas
+
+fn histogram_equalize(image: &mut Image, clip_limit: Option<f64>) {
+    let total = image.pixels.len() as f64;
+    let mut histogram: Vec<f64> = image.histogram().iter().map(|&count| count as f64).collect();
+
+    if let Some(limit) = clip_limit {
+        let cap = (limit * total / 256.0).max(1.0);
+        let mut excess = 0.0;
+        for bin in histogram.iter_mut() {
+            if *bin > cap {
+                excess += *bin - cap;
+                *bin = cap;
+            }
+        }
+        for bin in histogram.iter_mut() {
+            *bin += excess / 256.0;
+        }
+    }
+
+    let mut cdf = [0.0; 256];
+    let mut running = 0.0;
+    for (v, bin) in histogram.iter().enumerate() {
+        running += bin;
+        cdf[v] = running;
+    }
+
+    let cdf_min = cdf.iter().cloned().find(|&c| c > 0.0).unwrap_or(0.0);
+    if total - cdf_min <= 0.0 {
+        return;
+    }
+
+    for pixel in image.pixels.iter_mut() {
+        let (y, cb, cr) = pixel.to_ycbcr();
+        let mapped = (cdf[clamp_channel(y) as usize] - cdf_min) / (total - cdf_min) * 255.0;
+        let alpha = pixel.alpha;
+        *pixel = Pixel::from_ycbcr(mapped, cb, cr);
+        pixel.alpha = alpha;
+    }
+}
+
+// Gray world white balance: scale each channel so its mean matches the mean
+// of all three, on the assumption that the scene averages out to gray
+
+fn gray_world_balance(image: &mut Image) {
+    let mut sums = [0.0; 3];
+    for pixel in image.pixels.iter() {
+        sums[0] += pixel.red as f64;
+        sums[1] += pixel.green as f64;
+        sums[2] += pixel.blue as f64;
+    }
+
+    let overall = (sums[0] + sums[1] + sums[2]) / 3.0;
+    let scale = |sum: f64| if sum > 0.0 { overall / sum } else { 1.0 };
+    let (red, green, blue) = (scale(sums[0]), scale(sums[1]), scale(sums[2]));
+
+    for pixel in image.pixels.iter_mut() {
+        let (r, g, b) = (
+            clamp_channel(pixel.red as f64 * red),
+            clamp_channel(pixel.green as f64 * green),
+            clamp_channel(pixel.blue as f64 * blue),
+        );
+        pixel.set_rgb(r, g, b);
+    }
+}
+
+// Dedicated 3x3 mean with clamped edges. Each column keeps the sum of its
+// three rows, sliding down a row at a time by adding the entering row and
+// dropping the leaving one, then a running sum slides along the row the
+// same way over those column sums
+
+fn box_blur_3x3(image: &mut Image) {
+    let original = image.pixels.to_vec();
+    let (width, height) = (image.width as isize, image.height as isize);
+    let mut columns = vec![[0u32; 3]; image.width];
+
+    for row in 0..height {
+        for (col, sum) in columns.iter_mut().enumerate() {
+            let channels = |r: isize| {
+                let pix = original[image.clamped_index(r, col as isize)];
+                [pix.red as u32, pix.green as u32, pix.blue as u32]
+            };
+
+            if row == 0 {
+                *sum = [0; 3];
+                for r in -1..2 {
+                    for (total, value) in sum.iter_mut().zip(channels(r).iter()) {
+                        *total += value;
+                    }
+                }
+            } else {
+                let (entering, leaving) = (channels(row + 1), channels(row - 2));
+                for (channel, total) in sum.iter_mut().enumerate() {
+                    *total = *total + entering[channel] - leaving[channel];
+                }
+            }
+        }
+
+        let column = |c: isize| columns[c.clamp(0, width - 1) as usize];
+        let mut running = [0u32; 3];
+        for c in -1..2 {
+            for (total, value) in running.iter_mut().zip(column(c).iter()) {
+                *total += value;
+            }
+        }
+
+        for col in 0..width {
+            if col > 0 {
+                let (entering, leaving) = (column(col + 1), column(col - 2));
+                for (channel, total) in running.iter_mut().enumerate() {
+                    *total = *total + entering[channel] - leaving[channel];
+                }
+            }
+
+            let average = |total: u32| clamp_channel(total as f64 / 9.0);
+            let i = image.row_col_to_index(row as usize, col as usize);
+            image.pixels[i].set_rgb(average(running[0]), average(running[1]), average(running[2]));
+        }
+    }
+}
+
+// Gaussian blur inside the (x, y, width, height) rectangle only. The blur
+// runs on a copy padded by the kernel radius, so pixels near the rectangle's
+// edge are blurred with their real neighbours instead of clamped ones
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn blur_region(image: &mut Image, rect: (usize, usize, usize, usize), sigma: f64) {
+    let (x, y, width, height) = rect;
+    let right = (x + width).min(image.width);
+    let bottom = (y + height).min(image.height);
+    if x >= right || y >= bottom {
+        return;
+    }
+
+    let pad = (sigma * 3.0).ceil() as usize;
+    let (left, top) = (x.saturating_sub(pad), y.saturating_sub(pad));
+    let (padded_right, padded_bottom) = ((right + pad).min(image.width), (bottom + pad).min(image.height));
+
+    let (mut padded, padded_width, padded_height) = image.crop(left, top, padded_right - left, padded_bottom - top);
+    gaussian_blur(
+        &mut Image {
+            width: padded_width,
+            height: padded_height,
+            pixels: &mut padded[..],
+        },
+        sigma,
+    );
+
+    for row in y..bottom {
+        for col in x..right {
+            let i = image.row_col_to_index(row, col);
+            image.pixels[i] = padded[(row - top) * padded_width + (col - left)];
+        }
+    }
+}
+
+// Round each channel to the nearest of `levels` evenly spaced values
+
+fn posterize(image: &mut Image, levels: u8) {
+    if levels < 2 {
+        return;
+    }
+
+    let step = 255.0 / (levels - 1) as f64;
+    let snap = |v: u8| clamp_channel((v as f64 / step).round() * step);
+    for pixel in image.pixels.iter_mut() {
+        let (red, green, blue) = (snap(pixel.red), snap(pixel.green), snap(pixel.blue));
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+// Quantize CIE L* into evenly spaced levels, then scale the pixel in linear
+// light so it lands on the quantized lightness. Steps come out perceptually
+// even, where RGB steps bunch up in the highlights
+
+fn posterize_perceptual(image: &mut Image, levels: u8) {
+    if levels < 2 {
+        return;
+    }
+
+    let step = 100.0 / (levels - 1) as f64;
+    for pixel in image.pixels.iter_mut() {
+        let linear = [srgb_to_linear(pixel.red), srgb_to_linear(pixel.green), srgb_to_linear(pixel.blue)];
+        let luminance = pixel.linear_luminance();
+        let target = lightness_to_linear((linear_to_lightness(luminance) / step).round() * step);
+
+        if luminance <= 0.0 {
+            pixel.set_gray(linear_to_srgb(target));
+            continue;
+        }
+
+        let scale = target / luminance;
+        pixel.set_rgb(
+            linear_to_srgb(linear[0] * scale),
+            linear_to_srgb(linear[1] * scale),
+            linear_to_srgb(linear[2] * scale),
+        );
+    }
+}
+
+fn simulate_color_blindness(image: &mut Image, kind: ColorBlindType) {
+    for pixel in image.pixels.iter_mut() {
+        *pixel = pixel.simulate_color_blindness(kind);
+    }
+}
+
+// Shifts the detail a dichromat loses (original minus simulated) into the
+// green and blue channels, where it remains visible to them
+
+fn daltonize(image: &mut Image, kind: ColorBlindType) {
+    for pixel in image.pixels.iter_mut() {
+        let simulated = pixel.simulate_color_blindness(kind);
+        let error_red = pixel.red as f64 - simulated.red as f64;
+        let error_green = pixel.green as f64 - simulated.green as f64;
+        let error_blue = pixel.blue as f64 - simulated.blue as f64;
+
+        let green = pixel.green as f64 + 0.7 * error_red + error_green;
+        let blue = pixel.blue as f64 + 0.7 * error_red + error_blue;
+        pixel.green = clamp_channel(green);
+        pixel.blue = clamp_channel(blue);
+    }
+}
+
+// Treats luminance as a 0..1 heightmap and encodes the tangent-space normal
+// of each pixel as RGB. Sobel sums span 8 pixels of weight, hence the 8
+
+fn normal_map(image: &mut Image, strength: f64) {
+    let gradients = image.sobel_gradients();
+
+    for (pixel, &(gx, gy)) in image.pixels.iter_mut().zip(gradients.iter()) {
+        let dx = gx / (8.0 * 255.0) * strength;
+        let dy = gy / (8.0 * 255.0) * strength;
+        let length = (dx * dx + dy * dy + 1.0).sqrt();
+
+        let encode = |n: f64| clamp_channel((n / length + 1.0) * 127.5);
+        pixel.set_rgb(encode(-dx), encode(-dy), encode(1.0));
+    }
+}
+
+fn bit_plane(image: &mut Image, bit: u8) {
+    let plane = |v: u8| if BitFlags::new(v).get(bit as usize) { 255 } else { 0 };
+
+    for pixel in image.pixels.iter_mut() {
+        pixel.set_rgb(plane(pixel.red), plane(pixel.green), plane(pixel.blue));
+    }
+}
+
+// Averages bilinear samples along the line from each pixel back towards the
+// center, covering strength times the distance. The sample count grows with
+// that span so far pixels don't band
+
+fn radial_blur(image: &mut Image, strength: f64, center: (f64, f64)) {
+    let mut pixels_copy = image.pixels.to_vec();
+    let original = Image {
+        width: image.width,
+        height: image.height,
+        pixels: &mut pixels_copy,
+    };
+
+    for (i, pixel) in image.pixels.iter_mut().enumerate() {
+        let (row, col) = original.index_to_row_col(i);
+        let (dx, dy) = (col as f64 - center.0, row as f64 - center.1);
+        let span = (dx * dx + dy * dy).sqrt() * strength;
+        let samples = (span.ceil() as usize).clamp(1, 32);
+
+        let mut totals = [0.0; 4];
+        for s in 0..samples {
+            let scale = 1.0 - strength * s as f64 / samples as f64;
+            let p = original.sample_bilinear(center.0 + dx * scale, center.1 + dy * scale);
+            totals[0] += p.red as f64;
+            totals[1] += p.green as f64;
+            totals[2] += p.blue as f64;
+            totals[3] += p.alpha as f64;
+        }
+
+        let n = samples as f64;
+        *pixel = Pixel::rgba(
+            clamp_channel(totals[0] / n),
+            clamp_channel(totals[1] / n),
+            clamp_channel(totals[2] / n),
+            clamp_channel(totals[3] / n),
+        );
+    }
+}
+
+fn clipping_warning(image: &mut Image, high: u8, low: u8, over_color: Pixel, under_color: Pixel) {
+    for pixel in image.pixels.iter_mut() {
+        let luminance = pixel.luminance();
+        if luminance > high {
+            *pixel = over_color;
+        } else if luminance < low {
+            *pixel = under_color;
+        }
+    }
+}
+
+fn hue_rotate(image: &mut Image, degrees: f64) {
+    for pixel in image.pixels.iter_mut() {
+        *pixel = pixel.hue_rotated(degrees);
+    }
+}
+
+// Monochrome grain from seeded triangular noise. A size above zero clumps it
+// with a gaussian of that sigma, rescaled back to the original spread, and
+// each pixel's share peaks in the midtones and fades at black and white
+
+fn film_grain(image: &mut Image, intensity: f64, size: f64, seed: u64) {
+    if intensity == 0.0 {
+        return;
+    }
+
+    let mut rng = Rng::new(seed);
+    let mut noise: Vec<f64> = (0..image.pixels.len()).map(|_| rng.next_f64() + rng.next_f64() - 1.0).collect();
+
+    if size > 0.0 {
+        let spread = |values: &[f64]| (values.iter().map(|v| v * v).sum::<f64>() / values.len() as f64).sqrt();
+        let before = spread(&noise);
+        noise = image.smooth_values(&noise, size);
+        let after = spread(&noise);
+        if after > 0.0 {
+            for n in noise.iter_mut() {
+                *n *= before / after;
+            }
+        }
+    }
+
+    for (pixel, n) in image.pixels.iter_mut().zip(noise.iter()) {
+        let l = pixel.luminance_f64() / 255.0;
+        let amount = n * intensity * 255.0 * 4.0 * l * (1.0 - l);
+        pixel.set_rgb(
+            clamp_channel(pixel.red as f64 + amount),
+            clamp_channel(pixel.green as f64 + amount),
+            clamp_channel(pixel.blue as f64 + amount),
+        );
+    }
+}
+
+// Darkens rows 0, spacing, 2 * spacing and so on, scaling their channels by
+// 1 - darkness so a darkness of 1 makes them black
+
+fn scanlines(image: &mut Image, spacing: usize, darkness: f64) {
+    if spacing == 0 || image.width == 0 {
+        return;
+    }
+
+    let factor = 1.0 - darkness;
+    for row in image.pixels.chunks_mut(image.width).step_by(spacing) {
+        for pixel in row.iter_mut() {
+            pixel.set_rgb(
+                clamp_channel(pixel.red as f64 * factor),
+                clamp_channel(pixel.green as f64 * factor),
+                clamp_channel(pixel.blue as f64 * factor),
+            );
+        }
+    }
+}
+
+// Dilates the non-transparent silhouette by a disc of radius thickness and
+// composites the image over color wherever the dilated shape reaches, so the
+// stroke surrounds the shape from behind
+
+fn outline(image: &mut Image, thickness: usize, color: Pixel) {
+    if thickness == 0 {
+        return;
+    }
+
+    let original = image.pixels.to_vec();
+    let reach = thickness as isize;
+
+    for i in 0..original.len() {
+        if original[i].alpha == 255 {
+            continue;
+        }
+
+        let (row, col) = image.index_to_row_col(i);
+        let mut covered = false;
+        'search: for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let (r, c) = (row as isize + dy, col as isize + dx);
+                if dx * dx + dy * dy > reach * reach || r < 0 || c < 0 || r >= image.height as isize || c >= image.width as isize {
+                    continue;
+                }
+
+                if original[image.row_col_to_index(r as usize, c as usize)].alpha > 0 {
+                    covered = true;
+                    break 'search;
+                }
+            }
+        }
+
+        if covered {
+            image.pixels[i] = original[i].over(&color);
+        }
+    }
+}
+
+// Snaps each channel to the nearest multiple of 51, giving the 216 colour
+// web-safe palette
+
+fn web_safe(image: &mut Image) {
+    let snap = |v: u8| ((v as u16 + 25) / 51 * 51) as u8;
+
+    for pixel in image.pixels.iter_mut() {
+        pixel.set_rgb(snap(pixel.red), snap(pixel.green), snap(pixel.blue));
+    }
+}
+
+// Edge-preserving smoothing: each neighbour within radius is weighted by its
+// spatial gaussian times a gaussian of its colour distance from the centre,
+// so pixels across a strong edge barely contribute
+
+fn bilateral(image: &mut Image, radius: usize, sigma_space: f64, sigma_range: f64) {
+    let original = image.pixels.to_vec();
+    let reach = radius as isize;
+
+    for i in 0..original.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let centre = original[i];
+        let mut totals = [0.0; 3];
+        let mut weight_total = 0.0;
+
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let neighbour = original[image.clamped_index(row as isize + dy, col as isize + dx)];
+                let dr = neighbour.red as f64 - centre.red as f64;
+                let dg = neighbour.green as f64 - centre.green as f64;
+                let db = neighbour.blue as f64 - centre.blue as f64;
+
+                let spatial = -((dx * dx + dy * dy) as f64) / (2.0 * sigma_space * sigma_space);
+                let range = -(dr * dr + dg * dg + db * db) / (2.0 * sigma_range * sigma_range);
+                let weight = (spatial + range).exp();
+
+                totals[0] += neighbour.red as f64 * weight;
+                totals[1] += neighbour.green as f64 * weight;
+                totals[2] += neighbour.blue as f64 * weight;
+                weight_total += weight;
+            }
+        }
+
+        image.pixels[i].set_rgb(
+            clamp_channel(totals[0] / weight_total),
+            clamp_channel(totals[1] / weight_total),
+            clamp_channel(totals[2] / weight_total),
+        );
+    }
+}
+
+// Bilateral passes flatten texture, posterizing leaves a few flat tones and
+// strong gradients of the smoothed image are inked in black on top
+
+fn cartoon_hq(image: &mut Image, bilateral_passes: usize, levels: u8) {
+    for _ in 0..bilateral_passes {
+        bilateral(image, 2, 2.0, 30.0);
+    }
+
+    let magnitude = image.gradient_magnitude();
+    posterize(image, levels);
+
+    for (pixel, m) in image.pixels.iter_mut().zip(magnitude.iter()) {
+        if *m > 150.0 {
+            pixel.set_gray(0);
+        }
+    }
+}
+
+// Naive CMYK separation with full black generation
+
+fn to_cmyk(pixel: &Pixel) -> [f64; 4] {
+    let (r, g, b) = (pixel.red as f64 / 255.0, pixel.green as f64 / 255.0, pixel.blue as f64 / 255.0);
+    let k = 1.0 - r.max(g).max(b);
+    if k >= 1.0 {
+        return [0.0, 0.0, 0.0, 1.0];
+    }
+
+    [(1.0 - r - k) / (1.0 - k), (1.0 - g - k) / (1.0 - k), (1.0 - b - k) / (1.0 - k), k]
+}
+
+// Each of C, M, Y and K is screened on its own grid rotated to the classic
+// 15, 75, 0 and 45 degrees. A pixel gets that ink when it lies inside the dot
+// of its cell, whose area is proportional to the tone at the cell centre
+
+fn color_halftone(image: &mut Image, cell: usize) {
+    if cell == 0 {
+        return;
+    }
+
+    let tones: Vec<[f64; 4]> = image.pixels.iter().map(to_cmyk).collect();
+    let angles = [15f64, 75.0, 0.0, 45.0];
+    let size = cell as f64;
+
+    for i in 0..tones.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let (x, y) = (col as f64 + 0.5, row as f64 + 0.5);
+        let mut ink = [0.0; 4];
+
+        for (channel, angle) in angles.iter().enumerate() {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let (u, v) = (x * cos + y * sin, -x * sin + y * cos);
+            let (cu, cv) = (((u / size).floor() + 0.5) * size, ((v / size).floor() + 0.5) * size);
+
+            let (cx, cy) = (cu * cos - cv * sin, cu * sin + cv * cos);
+            let centre = image.clamped_index(cy.floor() as isize, cx.floor() as isize);
+            let radius = size * (tones[centre][channel] / std::f64::consts::PI).sqrt();
+
+            if (u - cu).powi(2) + (v - cv).powi(2) < radius * radius {
+                ink[channel] = 1.0;
+            }
+        }
+
+        let channel = |c: f64| clamp_channel(255.0 * (1.0 - c) * (1.0 - ink[3]));
+        image.pixels[i].set_rgb(channel(ink[0]), channel(ink[1]), channel(ink[2]));
+    }
+}
+
+// Blends each channel towards the pixel's luminance, 0 leaving it untouched
+// and 1 giving luminance grayscale
+
+fn desaturate(image: &mut Image, factor: f64) {
+    for pixel in image.pixels.iter_mut() {
+        let luminance = pixel.luminance() as f64;
+        let blend = |v: u8| clamp_channel(v as f64 + (luminance - v as f64) * factor);
+        let (red, green, blue) = (blend(pixel.red), blend(pixel.green), blend(pixel.blue));
+        pixel.set_rgb(red, green, blue);
+    }
+}
+
+// The JPEG standard's example luminance quantization table
+
+static JPEG_QUANTIZATION: [[f64; 8]; 8] = [
+    [16.0, 11.0, 10.0, 16.0, 24.0, 40.0, 51.0, 61.0],
+    [12.0, 12.0, 14.0, 19.0, 26.0, 58.0, 60.0, 55.0],
+    [14.0, 13.0, 16.0, 24.0, 40.0, 57.0, 69.0, 56.0],
+    [14.0, 17.0, 22.0, 29.0, 51.0, 87.0, 80.0, 62.0],
+    [18.0, 22.0, 37.0, 56.0, 68.0, 109.0, 103.0, 77.0],
+    [24.0, 35.0, 55.0, 64.0, 81.0, 104.0, 113.0, 92.0],
+    [49.0, 64.0, 78.0, 87.0, 103.0, 121.0, 120.0, 101.0],
+    [72.0, 92.0, 95.0, 98.0, 112.0, 100.0, 103.0, 99.0],
+];
+
+// Row-wise then column-wise orthonormal 8 point DCT-II, or its inverse
+
+fn dct_8x8(block: &[[f64; 8]; 8], inverse: bool) -> [[f64; 8]; 8] {
+    let basis = |k: usize, n: usize| {
+        let scale = if k == 0 { (0.125f64).sqrt() } else { 0.5 };
+        scale * (std::f64::consts::PI * (2 * n + 1) as f64 * k as f64 / 16.0).cos()
+    };
+    let transform = |v: &[f64; 8]| {
+        let mut out = [0.0; 8];
+        for (i, o) in out.iter_mut().enumerate() {
+            *o = (0..8).map(|j| if inverse { basis(j, i) * v[j] } else { basis(i, j) * v[j] }).sum();
+        }
+        out
+    };
+
+    let mut rows = [[0.0; 8]; 8];
+    for (row, input) in rows.iter_mut().zip(block.iter()) {
+        *row = transform(input);
+    }
+
+    let mut out = [[0.0; 8]; 8];
+    for col in 0..8 {
+        let column: [f64; 8] = [0, 1, 2, 3, 4, 5, 6, 7].map(|r| rows[r][col]);
+        for (r, v) in transform(&column).iter().enumerate() {
+            out[r][col] = *v;
+        }
+    }
+    out
+}
+
+// Round trips every 8x8 block of each channel through a quantized DCT, with
+// the table scaled by quality the way libjpeg does (50 is the table as is,
+// 100 is near lossless). Edge blocks are padded by repeating border pixels
+
+fn dct_artifacts(image: &mut Image, quality: u8) {
+    let quality = quality.clamp(1, 100) as f64;
+    let scale = if quality < 50.0 { 5000.0 / quality } else { 200.0 - 2.0 * quality };
+    let mut table = [[0.0; 8]; 8];
+    for (row, base) in table.iter_mut().zip(JPEG_QUANTIZATION.iter()) {
+        for (q, b) in row.iter_mut().zip(base.iter()) {
+            *q = ((b * scale + 50.0) / 100.0).floor().clamp(1.0, 255.0);
+        }
+    }
+
+    let original = image.pixels.to_vec();
+    let channels: [fn(&Pixel) -> u8; 3] = [|p| p.red, |p| p.green, |p| p.blue];
+
+    for top in (0..image.height).step_by(8) {
+        for left in (0..image.width).step_by(8) {
+            let mut results = [[[0.0; 8]; 8]; 3];
+
+            for (channel, result) in channels.iter().zip(results.iter_mut()) {
+                let mut block = [[0.0; 8]; 8];
+                for (r, row) in block.iter_mut().enumerate() {
+                    for (c, v) in row.iter_mut().enumerate() {
+                        let i = image.clamped_index((top + r) as isize, (left + c) as isize);
+                        *v = channel(&original[i]) as f64 - 128.0;
+                    }
+                }
+
+                let mut coefficients = dct_8x8(&block, false);
+                for (row, q_row) in coefficients.iter_mut().zip(table.iter()) {
+                    for (v, q) in row.iter_mut().zip(q_row.iter()) {
+                        *v = (*v / q).round() * q;
+                    }
+                }
+                *result = dct_8x8(&coefficients, true);
+            }
+
+            let width = image.width;
+            for (r, row) in image.pixels.chunks_mut(width).skip(top).take(8).enumerate() {
+                for (c, pixel) in row[left..].iter_mut().take(8).enumerate() {
+                    let v = |channel: usize| clamp_channel(results[channel][r][c] + 128.0);
+                    pixel.set_rgb(v(0), v(1), v(2));
+                }
+            }
+        }
+    }
+}
+
+fn mean_luminance(image: &Image) -> f64 {
+    image.pixels.iter().map(|p| p.luminance_f64()).sum::<f64>() / image.pixels.len() as f64
+}
+
+// Darkens by strength * r^2, where r is the distance from the centre as a
+// fraction of the half diagonal. With preserve_brightness every channel is
+// then scaled so the mean luminance matches what it was beforehand, up to
+// whatever clipping at white takes away
+
+fn vignette(image: &mut Image, strength: f64, preserve_brightness: bool) {
+    if image.pixels.is_empty() {
+        return;
+    }
+
+    let before = mean_luminance(image);
+    let (cx, cy) = ((image.width as f64 - 1.0) / 2.0, (image.height as f64 - 1.0) / 2.0);
+    let reach = (cx * cx + cy * cy).sqrt().max(1.0);
+
+    let mut factors = Vec::with_capacity(image.pixels.len());
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let (dx, dy) = (col as f64 - cx, row as f64 - cy);
+        factors.push((1.0 - strength * (dx * dx + dy * dy) / (reach * reach)).max(0.0));
+    }
+
+    let mut values: Vec<[f64; 3]> = image
+        .pixels
+        .iter()
+        .zip(factors.iter())
+        .map(|(p, f)| [p.red as f64 * f, p.green as f64 * f, p.blue as f64 * f])
+        .collect();
+
+    if preserve_brightness {
+        let after = values.iter().map(|v| 0.299 * v[0] + 0.587 * v[1] + 0.114 * v[2]).sum::<f64>() / values.len() as f64;
+        if after > 0.0 {
+            let gain = before / after;
+            for v in values.iter_mut() {
+                for channel in v.iter_mut() {
+                    *channel *= gain;
+                }
+            }
+        }
+    }
+
+    for (pixel, v) in image.pixels.iter_mut().zip(values.iter()) {
+        pixel.set_rgb(clamp_channel(v[0]), clamp_channel(v[1]), clamp_channel(v[2]));
+    }
+}
+
+// Splits luminance at levels evenly spaced thresholds strictly between 0
+// and 255 and draws color over every pixel whose band differs from the band
+// of its right or lower neighbour, giving one line per threshold crossed
+
+fn contours(image: &mut Image, levels: u8, color: Pixel) {
+    let step = 256.0 / (levels as f64 + 1.0);
+    let bands: Vec<usize> = image
+        .pixels
+        .iter()
+        .map(|p| ((p.luminance_f64() / step) as usize).min(levels as usize))
+        .collect();
+
+    for i in 0..bands.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let right = col + 1 < image.width && bands[i + 1] != bands[i];
+        let below = row + 1 < image.height && bands[i + image.width] != bands[i];
+        if right || below {
+            image.pixels[i] = color;
+        }
+    }
+}
+
+// Blends a gradient running linearly from inner at the center to outer at
+// radius, and outer beyond it, onto the image with the given mode. Alpha is
+// interpolated too and acts as the gradient's opacity
+
+fn radial_gradient(image: &mut Image, center: (f64, f64), inner: Pixel, outer: Pixel, radius: f64, mode: BlendMode) {
+    let lerp = |a: u8, b: u8, t: f64| clamp_channel(a as f64 + (b as f64 - a as f64) * t);
+
+    for i in 0..image.pixels.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let (dx, dy) = (col as f64 - center.0, row as f64 - center.1);
+        let t = if radius > 0.0 { ((dx * dx + dy * dy).sqrt() / radius).min(1.0) } else { 1.0 };
+
+        let gradient = Pixel::rgba(
+            lerp(inner.red, outer.red, t),
+            lerp(inner.green, outer.green, t),
+            lerp(inner.blue, outer.blue, t),
+            lerp(inner.alpha, outer.alpha, t),
+        );
+        image.pixels[i] = image.pixels[i].blend(&gradient, mode);
+    }
+}
+
+// Keeps the top bits of each channel, clamped to 1..8, and stretches the
+// remaining levels back out over 0..255
+
+fn bit_depth(image: &mut Image, bits: u8) {
+    let bits = bits.clamp(1, 8);
+    let top = (1u32 << bits) - 1;
+    let reduce = |v: u8| ((v as u32 >> (8 - bits)) * 255 / top) as u8;
+
+    for pixel in image.pixels.iter_mut() {
+        pixel.set_rgb(reduce(pixel.red), reduce(pixel.green), reduce(pixel.blue));
+    }
+}
+
+// Variable blur driven by a map that is 0 over the band rows centred on
+// center_y and ramps linearly to full strength at the farthest row
+
+fn tilt_shift(image: &mut Image, center_y: usize, band: usize, max_sigma: f64) {
+    if image.height == 0 {
+        return;
+    }
+
+    let half = band / 2;
+    let reach = center_y.saturating_sub(half).max((image.height - 1).saturating_sub(center_y + half)).max(1);
+
+    let mut map: Vec<Pixel> = (0..image.height)
+        .flat_map(|row| {
+            let distance = (row as isize - center_y as isize).unsigned_abs().saturating_sub(half);
+            let level = clamp_channel(distance as f64 / reach as f64 * 255.0);
+            vec![Pixel::rgb(level, level, level); image.width]
+        })
+        .collect();
+
+    let blur_map = Image {
+        width: image.width,
+        height: image.height,
+        pixels: &mut map[..],
+    };
+    image.variable_blur(&blur_map, max_sigma);
+}
+
+// Median filters Cb and Cr over the (2 * radius + 1)^2 window while keeping
+// each pixel's own Y, so colour speckles go but luminance detail stays
+
+fn chroma_denoise(image: &mut Image, radius: usize) {
+    if radius == 0 {
+        return;
+    }
+
+    let ycbcr: Vec<(f64, f64, f64)> = image.pixels.iter().map(|p| p.to_ycbcr()).collect();
+    let reach = radius as isize;
+    let median = |values: &mut Vec<f64>| {
+        values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        values[values.len() / 2]
+    };
+
+    for i in 0..ycbcr.len() {
+        let (row, col) = image.index_to_row_col(i);
+        let mut cb = Vec::with_capacity((2 * radius + 1) * (2 * radius + 1));
+        let mut cr = Vec::with_capacity(cb.capacity());
+
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let j = image.clamped_index(row as isize + dy, col as isize + dx);
+                cb.push(ycbcr[j].1);
+                cr.push(ycbcr[j].2);
+            }
+        }
+
+        let alpha = image.pixels[i].alpha;
+        image.pixels[i] = Pixel::from_ycbcr(ycbcr[i].0, median(&mut cb), median(&mut cr));
+        image.pixels[i].alpha = alpha;
+    }
+}
+
+// Bilateral smoothing mixed in by amount, only where skin_mask finds skin
+
+fn skin_smooth(image: &mut Image, amount: f64) {
+    let mask = image.skin_mask();
+    let mut smoothed = image.pixels.to_vec();
+    bilateral(
+        &mut Image {
+            width: image.width,
+            height: image.height,
+            pixels: &mut smoothed[..],
+        },
+        3,
+        3.0,
+        25.0,
+    );
+
+    let mix = |a: u8, b: u8| clamp_channel(a as f64 + (b as f64 - a as f64) * amount);
+    for ((pixel, smooth), m) in image.pixels.iter_mut().zip(smoothed.iter()).zip(mask.iter()) {
+        if m.red == 255 {
+            let (red, green, blue) = (mix(pixel.red, smooth.red), mix(pixel.green, smooth.green), mix(pixel.blue, smooth.blue));
+            pixel.set_rgb(red, green, blue);
+        }
+    }
+}
+
+// Picks the gamma whose curve sends the mean luminance to mid-gray, solving
+// 255 * (mean / 255)^(1 / gamma) = 127.5. Images that are essentially all
+// black or all white have no usable solution and are left alone
+
+fn auto_gamma(image: &mut Image) {
+    if image.pixels.is_empty() {
+        return;
+    }
+
+    let mean = mean_luminance(image) / 255.0;
+    if !(0.01..=0.99).contains(&mean) {
+        return;
+    }
+
+    let gamma = (mean.ln() / 0.5f64.ln()).clamp(0.1, 10.0);
+    apply_gamma(image, gamma);
+}
+
+// Folds every angle around the centre into the first wedge of 2 * pi /
+// segments, mirroring alternate wedges so neighbours meet seamlessly, and
+// samples the original at the folded position
+
+fn radial_kaleidoscope(image: &mut Image, segments: usize) {
+    if segments == 0 {
+        return;
+    }
+
+    let mut pixels_copy = image.pixels.to_vec();
+    let original = Image {
+        width: image.width,
+        height: image.height,
+        pixels: &mut pixels_copy,
+    };
+
+    let (cx, cy) = ((image.width as f64 - 1.0) / 2.0, (image.height as f64 - 1.0) / 2.0);
+    let wedge = 2.0 * std::f64::consts::PI / segments as f64;
+
+    for (i, pixel) in image.pixels.iter_mut().enumerate() {
+        let (row, col) = original.index_to_row_col(i);
+        let (dx, dy) = (col as f64 - cx, row as f64 - cy);
+        let radius = (dx * dx + dy * dy).sqrt();
+
+        let angle = dy.atan2(dx).rem_euclid(2.0 * std::f64::consts::PI);
+        let k = (angle / wedge).floor();
+        let mut local = angle - k * wedge;
+        if k as usize % 2 == 1 {
+            local = wedge - local;
+        }
+
+        *pixel = original.sample_bilinear(cx + radius * local.cos(), cy + radius * local.sin());
+    }
+}
+
+// Unsharp masks Y alone, Y + amount * (Y - blurred Y), with a separable
+// gaussian of the given sigma truncated at radius. Cb and Cr pass through, so
+// edges gain contrast without colour fringes
+
+fn unsharp_luminance(image: &mut Image, radius: usize, sigma: f64, amount: f64) {
+    if radius == 0 || sigma <= 0.0 {
+        return;
+    }
+
+    let reach = radius as isize;
+    let mut kernel: Vec<f64> = (-reach..=reach).map(|x| (-((x * x) as f64) / (2.0 * sigma * sigma)).exp()).collect();
+    let total: f64 = kernel.iter().sum();
+    for weight in kernel.iter_mut() {
+        *weight /= total;
+    }
+
+    let ycbcr: Vec<(f64, f64, f64)> = image.pixels.iter().map(|p| p.to_ycbcr()).collect();
+    let blur = |values: &[f64], horizontal: bool| -> Vec<f64> {
+        (0..values.len())
+            .map(|i| {
+                let (row, col) = image.index_to_row_col(i);
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| {
+                        let offset = k as isize - reach;
+                        let j = if horizontal {
+                            image.clamped_index(row as isize, col as isize + offset)
+                        } else {
+                            image.clamped_index(row as isize + offset, col as isize)
+                        };
+                        values[j] * w
+                    })
+                    .sum()
+            })
+            .collect()
+    };
+
+    let luma: Vec<f64> = ycbcr.iter().map(|c| c.0).collect();
+    let blurred = blur(&blur(&luma, true), false);
+
+    for (i, &(y, cb, cr)) in ycbcr.iter().enumerate() {
+        let alpha = image.pixels[i].alpha;
+        image.pixels[i] = Pixel::from_ycbcr(y + amount * (y - blurred[i]), cb, cr);
+        image.pixels[i].alpha = alpha;
+    }
+}
+
+// Mean of values over the (2 * radius + 1)^2 window clipped to the image,
+// in constant time per pixel from a summed area table
+
+fn box_mean(values: &[f64], width: usize, height: usize, radius: usize) -> Vec<f64> {
+    let stride = width + 1;
+    let mut table = vec![0.0; stride * (height + 1)];
+    for row in 0..height {
+        for col in 0..width {
+            let i = (row + 1) * stride + col + 1;
+            table[i] = values[row * width + col] + table[i - 1] + table[i - stride] - table[i - stride - 1];
+        }
+    }
+
+    (0..values.len())
+        .map(|i| {
+            let (row, col) = (i / width, i % width);
+            let (top, left) = (row.saturating_sub(radius), col.saturating_sub(radius));
+            let (bottom, right) = ((row + radius + 1).min(height), (col + radius + 1).min(width));
+
+            let sum = table[bottom * stride + right] - table[top * stride + right] - table[bottom * stride + left]
+                + table[top * stride + left];
+            sum / ((bottom - top) * (right - left)) as f64
+        })
+        .collect()
+}
+
+// He et al.'s guided filter with each channel guiding itself. Per window the
+// output is a * I + b with a = var / (var + epsilon), so flat windows (small
+// variance against epsilon, on a 0..1 scale) are averaged away while strong
+// edges keep a near 1 and pass through. A flat window with epsilon 0 would
+// be 0 / 0, so it takes a = 0 and keeps its mean
+
+fn guided_filter(image: &mut Image, radius: usize, epsilon: f64) {
+    let (width, height) = (image.width, image.height);
+    let channels: [fn(&Pixel) -> u8; 3] = [|p| p.red, |p| p.green, |p| p.blue];
+    let mut outputs = Vec::with_capacity(3);
+
+    for channel in channels.iter() {
+        let values: Vec<f64> = image.pixels.iter().map(|p| channel(p) as f64 / 255.0).collect();
+        let squares: Vec<f64> = values.iter().map(|v| v * v).collect();
+
+        let mean = box_mean(&values, width, height, radius);
+        let mean_square = box_mean(&squares, width, height, radius);
+
+        let a: Vec<f64> = mean
+            .iter()
+            .zip(mean_square.iter())
+            .map(|(m, sq)| {
+                let variance = (sq - m * m).max(0.0);
+                if variance + epsilon > 0.0 {
+                    variance / (variance + epsilon)
+                } else {
+                    0.0
+                }
+            })
+            .collect();
+        let b: Vec<f64> = mean.iter().zip(a.iter()).map(|(m, a)| m * (1.0 - a)).collect();
+
+        let mean_a = box_mean(&a, width, height, radius);
+        let mean_b = box_mean(&b, width, height, radius);
+        let output: Vec<u8> =
+            values.iter().enumerate().map(|(i, v)| clamp_channel((mean_a[i] * v + mean_b[i]) * 255.0)).collect();
+        outputs.push(output);
+    }
+
+    for (i, pixel) in image.pixels.iter_mut().enumerate() {
+        pixel.set_rgb(outputs[0][i], outputs[1][i], outputs[2][i]);
+    }
+}
+
+// Local contrast on Y: the difference from a wide gaussian blur is squashed
+// through 20 * tanh(d / 20) so strong edges can't add much and halo, then
+// added back weighted by 4 * l * (1 - l) so midtones get the most. Cb and Cr
+// are kept, and negative amounts soften
+
+fn clarity(image: &mut Image, amount: f64) {
+    let ycbcr: Vec<(f64, f64, f64)> = image.pixels.iter().map(|p| p.to_ycbcr()).collect();
+    let luma: Vec<f64> = ycbcr.iter().map(|c| c.0).collect();
+    let blurred = image.smooth_values(&luma, 4.0);
+
+    for (i, &(y, cb, cr)) in ycbcr.iter().enumerate() {
+        let detail = 20.0 * ((y - blurred[i]) / 20.0).tanh();
+        let l = (y / 255.0).clamp(0.0, 1.0);
+        let weight = 4.0 * l * (1.0 - l);
+
+        let alpha = image.pixels[i].alpha;
+        image.pixels[i] = Pixel::from_ycbcr(y + amount * weight * detail, cb, cr);
+        image.pixels[i].alpha = alpha;
+    }
+}
+
+// 5x7 bitmaps for the usual ASCII art ramp characters, one row per byte
+// with the leftmost pixel in bit 4. Anything else renders blank
+
+fn glyph(c: char) -> [u8; 7] {
+    match c {
+        '.' => [0, 0, 0, 0, 0, 0b01100, 0b01100],
+        ':' => [0, 0b01100, 0b01100, 0, 0b01100, 0b01100, 0],
+        '-' => [0, 0, 0, 0b11111, 0, 0, 0],
+        '=' => [0, 0, 0b11111, 0, 0b11111, 0, 0],
+        '+' => [0, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0],
+        '*' => [0, 0b10101, 0b01110, 0b11111, 0b01110, 0b10101, 0],
+        '#' => [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010],
+        '%' => [0b11001, 0b11010, 0b00010, 0b00100, 0b01000, 0b01011, 0b10011],
+        '@' => [0b01110, 0b10001, 0b10111, 0b10101, 0b10111, 0b10000, 0b01111],
+        _ => [0; 7],
+    }
+}
+
+// Splits the image into cols columns of cells shaped like a 6x8 character box
+// (a 5x7 glyph plus spacing), picks a ramp character per cell from its mean
+// luminance, darkest first, and draws it black on white scaled to the cell
+
+fn ascii_art(image: &mut Image, cols: usize, ramp: &str) {
+    let ramp: Vec<char> = ramp.chars().collect();
+    if cols == 0 || ramp.is_empty() {
+        return;
+    }
+
+    let cell_width = (image.width / cols).max(1);
+    let cell_height = (cell_width * 8 / 6).max(1);
+
+    for top in (0..image.height).step_by(cell_height) {
+        for left in (0..image.width).step_by(cell_width) {
+            let (bottom, right) = ((top + cell_height).min(image.height), (left + cell_width).min(image.width));
+
+            let mut total = 0.0;
+            for row in top..bottom {
+                for col in left..right {
+                    total += image.pixels[image.row_col_to_index(row, col)].luminance_f64();
+                }
+            }
+            let mean = total / ((bottom - top) * (right - left)) as f64;
+            let bitmap = glyph(ramp[(mean / 255.0 * (ramp.len() - 1) as f64).round() as usize]);
+
+            for row in top..bottom {
+                for col in left..right {
+                    let (gy, gx) = ((row - top) * 8 / cell_height, (col - left) * 6 / cell_width);
+                    let ink = gy < 7 && gx < 5 && bitmap[gy] >> (4 - gx) & 1 == 1;
+                    let i = image.row_col_to_index(row, col);
+                    image.pixels[i].set_gray(if ink { 0 } else { 255 });
+                }
+            }
+        }
+    }
+}
+
+// Gives the pixel of rank r (by luminance, ties broken by position) the
+// target luminance 255 * r / (n - 1), so output levels are spread as evenly
+// as the pixel count allows. Channels are scaled together to keep hue, and
+// black pixels, which have no hue to keep, become gray
+
+fn rank_equalize(image: &mut Image) {
+    let n = image.pixels.len();
+    if n < 2 {
+        return;
+    }
+
+    let luminance: Vec<f64> = image.pixels.iter().map(|p| p.luminance_f64()).collect();
+    let mut order: Vec<usize> = (0..n).collect();
+    order.sort_by(|&a, &b| luminance[a].partial_cmp(&luminance[b]).unwrap());
+
+    for (rank, &i) in order.iter().enumerate() {
+        let target = 255.0 * rank as f64 / (n - 1) as f64;
+        let pixel = &mut image.pixels[i];
+        if luminance[i] <= 0.0 {
+            pixel.set_gray(clamp_channel(target));
+            continue;
+        }
+
+        let scale = target / luminance[i];
+        pixel.set_rgb(
+            clamp_channel(pixel.red as f64 * scale),
+            clamp_channel(pixel.green as f64 * scale),
+            clamp_channel(pixel.blue as f64 * scale),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use convolution::BLUR;
+
+    #[test]
+    fn test_filter_mirror_x() {
+        let mut pixels = [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::MirrorX);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+        ]);
+
+        let mut pixels = [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
+        image.filter(FilterType::MirrorX);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+        ]);
+    }
+
+    #[test]
+    fn test_filter_mirror_y() {
+        let mut pixels = [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::MirrorY);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+        ]);
+
+        let mut pixels = [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
+        image.filter(FilterType::MirrorY);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(100, 100, 100),
+        ]);
+    }
+
+    #[test]
+    fn test_filter_grayscale() {
+        let mut pixels = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::Grayscale);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(150, 150, 150),
+            Pixel::rgb(150, 150, 150),
+            Pixel::rgb(150, 150, 150),
+            Pixel::rgb(150, 150, 150),
+        ]);
+    }
+
+    #[test]
+    fn test_convolution() {
+        let mut pixels = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
+        image.filter(FilterType::Convolution([
+            [0.,0.,0.],
+            [0.,1.,0.],         // identity matrix
+            [0.,0.,0.],
+        ]));
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+        ]);
+
+        image.filter(FilterType::Convolution([
+            [0.,0.,0.],
+            [0.,0.,0.],         // zero out matrix
+            [0.,0.,0.],
+        ]));
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+        ]);
+    }
+
+    #[test]
+    fn test_invert() {
+        let mut pixels = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::Invert);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(155, 105, 55),
+            Pixel::rgb(155, 105, 55),
+            Pixel::rgb(155, 105, 55),
+            Pixel::rgb(155, 105, 55),
+        ]);
+    }
+
+    #[test]
+    fn test_chroma_subsample_420() {
+        let mut pixels = [
+            Pixel::rgb(200, 40, 40),
+            Pixel::rgb(40, 40, 200),
+            Pixel::rgb(40, 40, 200),
+            Pixel::rgb(200, 40, 40),
+        ];
+        let original = pixels;
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::ChromaSubsample420);
+
+        let (_, cb0, cr0) = image.pixels[0].to_ycbcr();
+        for (pixel, original) in image.pixels.iter().zip(original.iter()) {
+            let (y, cb, cr) = pixel.to_ycbcr();
+            let (original_y, _, _) = original.to_ycbcr();
+            assert!((y - original_y).abs() <= 1.0);
+            assert!((cb - cb0).abs() <= 1.0);
+            assert!((cr - cr0).abs() <= 1.0);
+        }
+
+        let (_, red_cb, _) = original[0].to_ycbcr();
+        let (_, blue_cb, _) = original[1].to_ycbcr();
+        assert!((cb0 - (red_cb + blue_cb) / 2.0).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_neon_edge() {
+        let mut source = Vec::new();
+        for _ in 0..8 {
+            for col in 0..8 {
+                source.push(if col < 4 { Pixel::rgb(0, 0, 0) } else { Pixel::rgb(255, 255, 255) });
+            }
+        }
+
+        let neon = Pixel::rgb(0, 255, 0);
+
+        let mut pixels = source.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 8, 8);
+        image.filter(FilterType::NeonEdge { color: neon, glow: 0 });
+
+        assert_eq!(image.pixels[3], neon);
+        assert_eq!(image.pixels[4], neon);
+        assert_eq!(image.pixels[1], Pixel::rgb(0, 0, 0));
+
+        let mut pixels = source.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 8, 8);
+        image.filter(FilterType::NeonEdge { color: neon, glow: 2 });
+
+        assert_eq!(image.pixels[3], neon);
+        assert!(image.pixels[1].green > 0);
+        assert_eq!(image.pixels[1].red, 0);
+        assert_eq!(image.pixels[1].blue, 0);
+    }
+
+    #[test]
+    fn test_curves() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(64, 64, 64),
+            Pixel::rgb(128, 128, 128),
+            Pixel::rgb(255, 255, 255),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::Curves {
+            red: vec![(128, 192)],
+            green: vec![],
+            blue: vec![],
+        });
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(96, 64, 64),
+            Pixel::rgb(192, 128, 128),
+            Pixel::rgb(255, 255, 255),
+        ]);
+    }
+
+    #[test]
+    fn test_pixelate() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 100, 100),
+            Pixel::rgb(50, 50, 50),
+            Pixel::rgb(10, 20, 30),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::Pixelate(2));
+
+        assert_eq!(image.pixels, [Pixel::rgb(40, 43, 45); 4]);
+    }
+
+    #[test]
+    fn test_retro() {
+        let mut pixels = [
+            Pixel::rgb(20, 20, 20),
+            Pixel::rgb(40, 40, 40),
+            Pixel::rgb(200, 200, 200),
+            Pixel::rgb(250, 250, 250),
+            Pixel::rgb(30, 30, 30),
+            Pixel::rgb(60, 60, 60),
+            Pixel::rgb(180, 180, 180),
+            Pixel::rgb(220, 220, 220),
+        ];
+
+        let black = Pixel::rgb(0, 0, 0);
+        let white = Pixel::rgb(255, 255, 255);
+
+        let mut image = Image::from_raw(&mut pixels[0], 4, 2);
+        image.filter(FilterType::Retro {
+            block: 2,
+            palette: vec![black, white],
+            dither: false,
+        });
+
+        assert_eq!(image.pixels, [
+            black, black, white, white,
+            black, black, white, white,
+        ]);
+    }
+
+    #[test]
+    fn test_ordered_dither() {
+        let mut pixels = [Pixel::rgb(128, 128, 128); 16];
+
+        let mut image = Image::from_raw(&mut pixels[0], 4, 4);
+        image.filter(FilterType::OrderedDither(2));
+
+        let black = Pixel::rgb(0, 0, 0);
+        let white = Pixel::rgb(255, 255, 255);
+
+        assert_eq!(image.pixels, [
+            white, black, white, black,
+            black, white, black, white,
+            white, black, white, black,
+            black, white, black, white,
+        ]);
+    }
+
+    #[test]
+    fn test_bayer() {
+        assert_eq!(
+            (0..4).map(|i| bayer(2, i / 2, i % 2)).collect::<Vec<_>>(),
+            vec![0, 2, 3, 1]
+        );
+
+        let mut values: Vec<usize> = (0..64).map(|i| bayer(8, i / 8, i % 8)).collect();
+        values.sort();
+        assert_eq!(values, (0..64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_filter_by_label() {
+        let mut pixels = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+        ];
+
+        let labels = [1, 0, 0, 1];
+        let mut map = HashMap::new();
+        map.insert(1, FilterType::Invert);
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter_by_label(&labels, map);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(155, 105, 55),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(155, 105, 55),
+        ]);
+    }
+
+    #[test]
+    fn test_filter_by_label_spatial_regions() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(90, 90, 90),
+            Pixel::rgb(180, 180, 180),
+            Pixel::rgb(30, 30, 30),
+            Pixel::rgb(240, 240, 240),
+            Pixel::rgb(60, 60, 60),
+        ];
+
+        // Label 1's bounding box covers label 2's column, so it must see the
+        // unfiltered column whichever label runs first
+        let labels = [1, 2, 1, 1, 2, 1];
+        let mut map = HashMap::new();
+        map.insert(1, FilterType::BoxBlur(1));
+        map.insert(2, FilterType::BoxBlur(1));
+
+        let mut image = Image::from_raw(&mut pixels[0], 3, 2);
+        image.filter_by_label(&labels, map);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(53, 53, 53),
+            Pixel::rgb(140, 140, 140),
+            Pixel::rgb(140, 140, 140),
+            Pixel::rgb(77, 77, 77),
+            Pixel::rgb(190, 190, 190),
+            Pixel::rgb(130, 130, 130),
+        ]);
+    }
+
+    #[test]
+    #[should_panic(expected = "one label per pixel")]
+    fn test_filter_by_label_length_mismatch() {
+        let mut pixels = [Pixel::rgb(100, 150, 200); 4];
+        let mut map = HashMap::new();
+        map.insert(1, FilterType::Invert);
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter_by_label(&[1, 0, 0, 1, 1], map);
+    }
+
+    #[test]
+    fn test_remove_background() {
+        let mut pixels = [
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(250, 252, 255),
+            Pixel::rgb(200, 30, 30),
+            Pixel::rgb(255, 255, 255),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::RemoveBackground {
+            color: Pixel::rgb(255, 255, 255),
+            tolerance: 8,
+        });
+
+        assert_eq!(image.pixels[0].alpha, 0);
+        assert_eq!(image.pixels[1].alpha, 0);
+        assert_eq!(image.pixels[2], Pixel::rgb(200, 30, 30));
+        assert_eq!(image.pixels[3].alpha, 0);
+    }
+
+    #[test]
+    fn test_name_and_describe() {
+        assert_eq!(FilterType::Invert.name(), "Invert");
+        assert_eq!(FilterType::Invert.describe(), "Invert filter");
+
+        assert_eq!(FilterType::OrderedDither(2).name(), "Ordered dither");
+        assert_eq!(FilterType::OrderedDither(2).describe(), "Ordered dither filter (size 2)");
+
+        let neon = FilterType::NeonEdge { color: Pixel::rgb(0, 255, 0), glow: 3 };
+        assert_eq!(neon.name(), "Neon edge");
+        assert_eq!(neon.describe(), "Neon edge filter (color rgb(0, 255, 0), glow 3)");
+
+        let retro = FilterType::Retro { block: 4, palette: vec![Pixel::rgb(0, 0, 0)], dither: true };
+        assert_eq!(retro.describe(), "Retro filter (block 4, palette size 1, dither on)");
+    }
+
+    #[test]
+    fn test_brightness() {
+        let mut pixels = [
+            Pixel::rgb(10, 100, 250),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::Brightness(20));
+        assert_eq!(image.pixels, [Pixel::rgb(30, 120, 255), Pixel::rgb(20, 20, 20)]);
+
+        image.filter(FilterType::Brightness(-40));
+        assert_eq!(image.pixels, [Pixel::rgb(0, 80, 215), Pixel::rgb(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_gamma() {
+        let mut pixels = [
+            Pixel::rgb(0, 64, 255),
+            Pixel::rgb(128, 128, 128),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::Gamma(1.0));
+        assert_eq!(image.pixels, [Pixel::rgb(0, 64, 255), Pixel::rgb(128, 128, 128)]);
+
+        image.filter(FilterType::Gamma(2.0));
+        assert_eq!(image.pixels, [Pixel::rgb(0, 128, 255), Pixel::rgb(181, 181, 181)]);
+    }
+
+    #[test]
+    fn test_gaussian_blur() {
+        let mut pixels = [Pixel::rgb(0, 0, 0); 9];
+        pixels[4] = Pixel::rgb(255, 255, 255);
+
+        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
+        image.filter(FilterType::GaussianBlur(1.0));
+
+        assert!(image.pixels[4].red < 255);
+        assert!(image.pixels[0].red > 0);
+        assert!(image.pixels[1].red > image.pixels[0].red);
+        assert_eq!(image.pixels[0], image.pixels[8]);
+    }
+
+    #[test]
+    fn test_sobel() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(255, 255, 255),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 4, 1);
+        image.filter(FilterType::Sobel);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(0, 0, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_commutes_with() {
+        assert!(FilterType::Brightness(10).commutes_with(&FilterType::Gamma(2.0)));
+        assert!(FilterType::MirrorX.commutes_with(&FilterType::Invert));
+        assert!(FilterType::MirrorX.commutes_with(&FilterType::MirrorY));
+        assert!(FilterType::Sobel.commutes_with(&FilterType::Sobel));
+
+        assert!(!FilterType::GaussianBlur(1.0).commutes_with(&FilterType::Sobel));
+        assert!(!FilterType::Sobel.commutes_with(&FilterType::Invert));
+        assert!(!FilterType::MirrorX.commutes_with(&FilterType::OrderedDither(2)));
+    }
+
+    #[test]
+    fn test_despeckle() {
+        let mut pixels = [Pixel::rgb(100, 100, 100); 25];
+        pixels[12] = Pixel::rgb(255, 0, 255);
+
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.filter(FilterType::Despeckle(30));
+
+        assert_eq!(image.pixels, [Pixel::rgb(100, 100, 100); 25]);
+
+        let mut pixels = Vec::new();
+        for _ in 0..4 {
+            for col in 0..8 {
+                pixels.push(if (col / 2) % 2 == 0 { Pixel::rgb(50, 50, 50) } else { Pixel::rgb(200, 200, 200) });
+            }
+        }
+        let original = pixels.clone();
+
+        let mut image = Image::from_raw(&mut pixels[0], 8, 4);
+        image.filter(FilterType::Despeckle(30));
+
+        assert_eq!(image.pixels.to_vec(), original);
+    }
+
+    #[test]
+    fn test_laplacian_of_gaussian() {
+        let mut pixels = vec![Pixel::rgb(0, 0, 0); 15 * 15];
+        for row in 6..9 {
+            for col in 6..9 {
+                pixels[row * 15 + col] = Pixel::rgb(255, 255, 255);
+            }
+        }
+
+        let mut image = Image::from_raw(&mut pixels[0], 15, 15);
+        image.filter(FilterType::LaplacianOfGaussian { sigma: 1.0 });
+
+        let centre = image.pixels[7 * 15 + 7].red;
+        assert!(centre > 200);
+        assert_eq!(image.pixels[0], Pixel::rgb(128, 128, 128));
+        assert!(image.pixels.iter().all(|p| p.red <= centre));
+    }
+
+    #[test]
+    fn test_convolve_alpha_aware() {
+        let clear = Pixel::rgba(0, 255, 0, 0);
+        let red = Pixel::rgb(255, 0, 0);
+        let source = [
+            clear, red, red,
+            clear, red, red,
+            clear, red, red,
+        ];
+
+        let mut pixels = source;
+        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
+        image.filter(FilterType::Convolution(BLUR));
+        assert!(image.pixels[4].green > 0);
+
+        let mut pixels = source;
+        let mut image = Image::from_raw(&mut pixels[0], 3, 3);
+        image.convolve_alpha_aware(BLUR);
+
+        assert_eq!(image.pixels[4].red, 255);
+        assert_eq!(image.pixels[4].green, 0);
+        assert_eq!(image.pixels[4].blue, 0);
+        assert_eq!(image.pixels[4].alpha, 191);
+    }
+
+    #[test]
+    fn test_auto_enhance() {
+        let mut pixels: Vec<Pixel> = (0..16)
+            .map(|i| Pixel::rgb(110 + i * 2, 105 + i * 2, 100 + i * 2))
+            .collect();
+        let original = pixels.clone();
+
+        let spread = |pixels: &[Pixel]| {
+            let luma: Vec<f64> = pixels.iter().map(|p| p.luminance_f64()).collect();
+            let mean = luma.iter().sum::<f64>() / luma.len() as f64;
+            luma.iter().map(|l| (l - mean) * (l - mean)).sum::<f64>() / luma.len() as f64
+        };
+        let saturation = |pixels: &[Pixel]| {
+            pixels
+                .iter()
+                .map(|p| (p.red.max(p.green).max(p.blue) - p.red.min(p.green).min(p.blue)) as f64)
+                .sum::<f64>() / pixels.len() as f64
+        };
+
+        let mut image = Image::from_raw(&mut pixels[0], 4, 4);
+        image.filter(FilterType::AutoEnhance);
+
+        assert!(spread(image.pixels) > spread(&original));
+        assert!(saturation(image.pixels) > saturation(&original));
+    }
+
+    #[test]
+    fn test_grid_overlay() {
+        let mut pixels = [Pixel::rgb(0, 0, 0); 25];
+        let red = Pixel::rgb(255, 0, 0);
+
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.filter(FilterType::GridOverlay { spacing: 2, color: red });
+
+        for i in 0..25 {
+            let (row, col) = image.index_to_row_col(i);
+            let on_grid = row % 2 == 0 || col % 2 == 0;
+            assert_eq!(image.pixels[i] == red, on_grid);
+        }
+        assert_eq!(image.pixels[6], Pixel::rgb(0, 0, 0));
+    }
+
+    #[test]
+    fn test_histogram_equalize() {
+        let source: Vec<Pixel> = (0..64)
+            .map(|i| {
+                let v = if i < 48 { 100 + (i % 4) as u8 } else { 90 + (i % 32) as u8 };
+                Pixel::rgb(v, v, v)
+            })
+            .collect();
+
+        let equalize = |clip_limit| {
+            let mut pixels = source.clone();
+            Image::from_raw(&mut pixels[0], 8, 8).filter(FilterType::HistogramEqualize { clip_limit });
+            pixels
+        };
+        let range = |pixels: &[Pixel]| {
+            let luma: Vec<u8> = pixels.iter().map(|p| p.luminance()).collect();
+            luma.iter().max().unwrap() - luma.iter().min().unwrap()
+        };
+
+        let plain = equalize(None);
+        assert_eq!(range(&plain), 255);
+        assert_eq!(equalize(Some(1000.0)), plain);
+
+        let gentle = equalize(Some(1.0));
+        assert!(range(&gentle) < range(&plain));
+        assert!(range(&gentle) > range(&source));
+    }
+
+    #[test]
+    fn test_gray_world_balance() {
+        let mut pixels = [
+            Pixel::rgb(80, 120, 80),
+            Pixel::rgb(100, 150, 100),
+            Pixel::rgb(60, 90, 60),
+            Pixel::rgb(120, 180, 120),
+        ];
+
+        let means = |pixels: &[Pixel]| {
+            let n = pixels.len() as f64;
+            (
+                pixels.iter().map(|p| p.red as f64).sum::<f64>() / n,
+                pixels.iter().map(|p| p.green as f64).sum::<f64>() / n,
+                pixels.iter().map(|p| p.blue as f64).sum::<f64>() / n,
+            )
+        };
+        let (red_before, green_before, _) = means(&pixels);
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.filter(FilterType::GrayWorldBalance);
+
+        let (red, green, blue) = means(image.pixels);
+        assert!(green - red < green_before - red_before);
+        assert!((green - red).abs() <= 1.0);
+        assert!((green - blue).abs() <= 1.0);
+    }
+
+    #[test]
+    fn test_box_blur_3x3() {
+        let source: Vec<Pixel> = (0..35)
+            .map(|i| Pixel::rgb((i * 37 % 256) as u8, (i * 11) as u8, (255 - i * 5) as u8))
+            .collect();
+
+        let mut fast = source.clone();
+        let mut image = Image::from_raw(&mut fast[0], 7, 5);
+        image.filter(FilterType::BoxBlur(1));
+
+        let mut general = source.clone();
+        let mut image = Image::from_raw(&mut general[0], 7, 5);
+        image.convolve_fn(1, |_, _| 1.0);
+
+        assert_eq!(fast, general);
+        assert_ne!(fast, source);
+    }
+
+    #[test]
+    fn test_blur_region() {
+        let mut pixels: Vec<Pixel> = (0..100)
+            .map(|i| if (i / 10 + i % 10) % 2 == 0 { Pixel::rgb(255, 255, 255) } else { Pixel::rgb(0, 0, 0) })
+            .collect();
+        let original = pixels.clone();
+
+        let mut image = Image::from_raw(&mut pixels[0], 10, 10);
+        image.blur_region((3, 3, 4, 4), 1.0);
+
+        for (i, original) in original.iter().enumerate() {
+            let (row, col) = image.index_to_row_col(i);
+            if (3..7).contains(&row) && (3..7).contains(&col) {
+                assert!(image.pixels[i].red > 64 && image.pixels[i].red < 192);
+            } else {
+                assert_eq!(image.pixels[i], *original);
+            }
+        }
+    }
+
+    #[test]
+    fn test_posterize() {
+        let mut pixels = [Pixel::rgb(0, 40, 50), Pixel::rgb(127, 128, 255)];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::Posterize(3));
+
+        assert_eq!(image.pixels, [Pixel::rgb(0, 0, 0), Pixel::rgb(128, 128, 255)]);
+    }
+
+    #[test]
+    fn test_posterize_perceptual() {
+        let gradient: Vec<Pixel> = (0..256).map(|v| Pixel::rgb(v as u8, v as u8, v as u8)).collect();
+
+        let step_spread = |filter: FilterType| {
+            let mut pixels = gradient.clone();
+            Image::from_raw(&mut pixels[0], 256, 1).filter(filter);
+
+            let mut levels: Vec<f64> = pixels.iter().map(|p| p.lightness()).collect();
+            levels.dedup_by(|a, b| (*a - *b).abs() < 1e-9);
+            assert_eq!(levels.len(), 4);
+
+            let steps: Vec<f64> = levels.windows(2).map(|pair| pair[1] - pair[0]).collect();
+            let largest = steps.iter().cloned().fold(0.0, f64::max);
+            let smallest = steps.iter().cloned().fold(100.0, f64::min);
+            largest - smallest
+        };
+
+        let perceptual = step_spread(FilterType::PosterizePerceptual(4));
+        let plain = step_spread(FilterType::Posterize(4));
+
+        assert!(perceptual < plain);
+        assert!(perceptual < 1.0);
+    }
+
+    #[test]
+    fn test_simulate_color_blindness() {
+        let distance = |a: Pixel, b: Pixel| {
+            let (dr, dg, db) = (a.red as f64 - b.red as f64, a.green as f64 - b.green as f64, a.blue as f64 - b.blue as f64);
+            (dr * dr + dg * dg + db * db).sqrt()
+        };
+
+        let mut pixels = [Pixel::rgb(200, 40, 40), Pixel::rgb(40, 160, 40)];
+        let before = distance(pixels[0], pixels[1]);
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::SimulateColorBlindness(ColorBlindType::Protanopia));
+
+        let after = distance(image.pixels[0], image.pixels[1]);
+        assert!(after < before / 2.0);
+    }
+
+    #[test]
+    fn test_daltonize() {
+        let distance = |a: Pixel, b: Pixel| {
+            let (dr, dg, db) = (a.red as f64 - b.red as f64, a.green as f64 - b.green as f64, a.blue as f64 - b.blue as f64);
+            (dr * dr + dg * dg + db * db).sqrt()
+        };
+        let kind = ColorBlindType::Deuteranopia;
+
+        let original = [Pixel::rgb(200, 60, 60), Pixel::rgb(60, 140, 60)];
+        let before = distance(original[0].simulate_color_blindness(kind), original[1].simulate_color_blindness(kind));
+
+        let mut pixels = original;
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::Daltonize(kind));
+
+        let after = distance(image.pixels[0].simulate_color_blindness(kind), image.pixels[1].simulate_color_blindness(kind));
+        assert!(after > before);
+    }
+
+    #[test]
+    fn test_normal_map() {
+        let mut pixels = [Pixel::rgb(90, 90, 90); 16];
+        let mut image = Image::from_raw(&mut pixels[0], 4, 4);
+        image.filter(FilterType::NormalMap { strength: 2.0 });
+
+        assert!(image.pixels.iter().all(|p| (p.red, p.green, p.blue) == (128, 128, 255)));
+    }
+
+    #[test]
+    fn test_bit_plane() {
+        let mut pixels = [Pixel::rgb(200, 100, 1)];
+        let mut image = Image::from_raw(&mut pixels[0], 1, 1);
+        image.filter(FilterType::BitPlane(7));
+
+        assert_eq!((image.pixels[0].red, image.pixels[0].green, image.pixels[0].blue), (255, 0, 0));
+
+        let mut pixels = [Pixel::rgb(200, 100, 1)];
+        let mut image = Image::from_raw(&mut pixels[0], 1, 1);
+        image.filter(FilterType::BitPlane(0));
+
+        assert_eq!((image.pixels[0].red, image.pixels[0].green, image.pixels[0].blue), (0, 0, 255));
+    }
+
+    #[test]
+    fn test_radial_blur() {
+        let make = || -> Vec<Pixel> { (0..25).map(|i| Pixel::rgb((i * 37 % 256) as u8, (i * 11) as u8, 200)).collect() };
+
+        let mut pixels = make();
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.filter(FilterType::RadialBlur { strength: 0.0, center: (2.0, 2.0) });
+        assert_eq!(pixels, make());
+
+        let mut pixels = make();
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.filter(FilterType::RadialBlur { strength: 0.5, center: (2.0, 2.0) });
+        assert_eq!(image.pixels[12], make()[12]);
+        assert_ne!(image.pixels[0], make()[0]);
+    }
+
+    #[test]
+    fn test_clipping_warning() {
+        let over_color = Pixel::rgb(255, 0, 0);
+        let under_color = Pixel::rgb(0, 0, 255);
+
+        let mut pixels = [Pixel::rgb(255, 255, 255), Pixel::rgb(0, 0, 0), Pixel::rgb(128, 128, 128)];
+        let mut image = Image::from_raw(&mut pixels[0], 3, 1);
+        image.filter(FilterType::ClippingWarning { high: 250, low: 5, over_color, under_color });
+
+        assert_eq!(image.pixels, &[over_color, under_color, Pixel::rgb(128, 128, 128)]);
+    }
+
+    #[test]
+    fn test_film_grain() {
+        let make = || -> Vec<Pixel> { (0..64).map(|i| Pixel::rgb(60 + i as u8, 128, 190 - i as u8)).collect() };
+        let grain = |seed: u64, intensity: f64| {
+            let mut pixels = make();
+            Image::from_raw(&mut pixels[0], 8, 8).filter(FilterType::FilmGrain { intensity, size: 1.0, seed });
+            pixels
+        };
+
+        assert_eq!(grain(7, 0.2), grain(7, 0.2));
+        assert_ne!(grain(7, 0.2), grain(8, 0.2));
+        assert_ne!(grain(7, 0.2), make());
+        assert_eq!(grain(7, 0.0), make());
+    }
+
+    #[test]
+    fn test_scanlines() {
+        let mut pixels = [Pixel::rgb(200, 100, 50); 12];
+        let mut image = Image::from_raw(&mut pixels[0], 2, 6);
+        image.filter(FilterType::Scanlines { spacing: 3, darkness: 0.25 });
+
+        for (i, pixel) in image.pixels.iter().enumerate() {
+            if (i / 2) % 3 == 0 {
+                assert_eq!(*pixel, Pixel::rgb(150, 75, 38));
+            } else {
+                assert_eq!(*pixel, Pixel::rgb(200, 100, 50));
+            }
+        }
+
+        let mut empty = Image { width: 0, height: 0, pixels: &mut [] };
+        empty.filter(FilterType::Scanlines { spacing: 3, darkness: 0.25 });
+    }
+
+    #[test]
+    fn test_outline() {
+        let red = Pixel::rgb(255, 0, 0);
+        let color = Pixel::rgb(255, 255, 255);
+        let clear = Pixel::rgba(0, 0, 0, 0);
+
+        let mut pixels = [clear; 81];
+        for &(x, y) in &[(4, 4), (4, 3), (4, 5), (3, 4), (5, 4)] {
+            pixels[y * 9 + x] = red;
+        }
+        let mut image = Image::from_raw(&mut pixels[0], 9, 9);
+        image.filter(FilterType::Outline { thickness: 2, color });
+
+        let at = |x: usize, y: usize| image.pixels[y * 9 + x];
+        assert_eq!(at(4, 4), red);
+        assert_eq!(at(3, 4), red);
+        assert_eq!(at(2, 4), color);
+        assert_eq!(at(1, 4), color);
+        assert_eq!(at(0, 4), clear);
+        assert_eq!(at(4, 7), color);
+        assert_eq!(at(4, 8), clear);
+        assert_eq!(at(2, 3), color);
+        assert_eq!(at(2, 2), clear);
+    }
+
+    #[test]
+    fn test_web_safe() {
+        let mut pixels = [Pixel::rgb(120, 130, 255), Pixel::rgb(0, 25, 26)];
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::WebSafe);
+
+        assert_eq!(image.pixels, &[Pixel::rgb(102, 153, 255), Pixel::rgb(0, 0, 51)]);
+    }
+
+    #[test]
+    fn test_cartoon_hq() {
+        let mut pixels: Vec<Pixel> = (0..96)
+            .map(|i| {
+                let (row, col) = (i / 12, i % 12);
+                let noise = if (row + col) % 2 == 0 { 8 } else { 0 };
+                if col < 6 {
+                    Pixel::rgb(60 + noise, 100 + noise, 60)
+                } else {
+                    Pixel::rgb(220 - noise, 200, 180 + noise)
+                }
+            })
+            .collect();
+        let mut image = Image::from_raw(&mut pixels[0], 12, 8);
+        image.filter(FilterType::CartoonHQ { bilateral_passes: 3, levels: 4 });
+
+        let at = |x: usize, y: usize| image.pixels[y * 12 + x];
+        for y in 0..8 {
+            for x in (0..4).chain(8..12) {
+                assert_eq!(at(x, y), at(if x < 4 { 0 } else { 11 }, 0));
+            }
+            assert!(at(5, y) == Pixel::rgb(0, 0, 0) || at(6, y) == Pixel::rgb(0, 0, 0));
+        }
+        assert_ne!(at(0, 0), at(11, 0));
+    }
+
+    #[test]
+    fn test_color_halftone() {
+        let mut pixels: Vec<Pixel> =
+            (0..400).map(|i| if i % 20 < 10 { Pixel::rgb(0, 255, 255) } else { Pixel::rgb(255, 255, 255) }).collect();
+        let mut image = Image::from_raw(&mut pixels[0], 20, 20);
+        image.filter(FilterType::ColorHalftone { cell: 4 });
+
+        let (cyan, white) = (Pixel::rgb(0, 255, 255), Pixel::rgb(255, 255, 255));
+        let left: Vec<Pixel> = (2..18).flat_map(|y| (0..7).map(move |x| y * 20 + x)).map(|i| image.pixels[i]).collect();
+        let right: Vec<Pixel> = (2..18).flat_map(|y| (13..20).map(move |x| y * 20 + x)).map(|i| image.pixels[i]).collect();
+
+        assert!(left.iter().all(|&p| p == cyan || p == white));
+        assert!(left.iter().filter(|&&p| p == cyan).count() > left.len() / 2);
+        assert!(right.iter().all(|&p| p == white));
+    }
+
+    #[test]
+    fn test_gamma_rgb() {
+        let mut pixels = [Pixel::rgb(100, 100, 100), Pixel::rgb(200, 50, 150)];
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        image.filter(FilterType::GammaRGB { r: 2.0, g: 1.0, b: 1.0 });
+
+        assert!(image.pixels[0].red > 100 && image.pixels[1].red > 200);
+        assert_eq!((image.pixels[0].green, image.pixels[0].blue), (100, 100));
+        assert_eq!((image.pixels[1].green, image.pixels[1].blue), (50, 150));
+    }
+
+    #[test]
+    fn test_desaturate() {
+        let original = [Pixel::rgb(200, 100, 40), Pixel::rgb(10, 250, 90)];
+        let desaturated = |factor: f64| {
+            let mut pixels = original;
+            Image::from_raw(&mut pixels[0], 2, 1).filter(FilterType::Desaturate(factor));
+            pixels
+        };
+
+        assert_eq!(desaturated(0.0), original);
+
+        let gray = desaturated(1.0);
+        for (p, o) in gray.iter().zip(original.iter()) {
+            assert_eq!((p.red, p.green, p.blue), (o.luminance(), o.luminance(), o.luminance()));
+        }
+
+        let half = desaturated(0.5);
+        let luminance = original[0].luminance() as f64;
+        assert_eq!(half[0].red, clamp_channel((200.0 + luminance) / 2.0));
+        assert_eq!(half[0].green, clamp_channel((100.0 + luminance) / 2.0));
+        assert_eq!(half[0].blue, clamp_channel((40.0 + luminance) / 2.0));
+    }
+
+    #[test]
+    fn test_dct_artifacts() {
+        let original: Vec<Pixel> =
+            (0..256).map(|i| Pixel::rgb((i * 7 % 256) as u8, (i % 16 * 16) as u8, ((i / 16) * 13) as u8)).collect();
+        let mse = |quality: u8| {
+            let mut pixels = original.clone();
+            Image::from_raw(&mut pixels[0], 16, 16).filter(FilterType::DctArtifacts { quality });
+            pixels
+                .iter()
+                .zip(original.iter())
+                .map(|(a, b)| {
+                    let d = [a.red as f64 - b.red as f64, a.green as f64 - b.green as f64, a.blue as f64 - b.blue as f64];
+                    d.iter().map(|v| v * v).sum::<f64>()
+                })
+                .sum::<f64>()
+                / original.len() as f64
+        };
+
+        assert!(mse(10) > mse(50));
+        assert!(mse(50) > mse(95));
+        assert!(mse(100) < 1.0);
+    }
+
+    #[test]
+    fn test_vignette() {
+        let original: Vec<Pixel> = (0..100).map(|i| Pixel::rgb(100 + i as u8, 120, 90)).collect();
+        let vignetted = |preserve_brightness: bool| {
+            let mut pixels = original.clone();
+            let mut image = Image::from_raw(&mut pixels[0], 10, 10);
+            image.filter(FilterType::Vignette { strength: 0.6, preserve_brightness });
+            (mean_luminance(&image), image.pixels[0].red, image.pixels[55].red)
+        };
+
+        let mut pixels = original.clone();
+        let before = mean_luminance(&Image::from_raw(&mut pixels[0], 10, 10));
+
+        let (darkened, corner, centre) = vignetted(false);
+        assert!(darkened < before * 0.85);
+        assert!(corner < centre);
+
+        let (preserved, corner, centre) = vignetted(true);
+        assert!((preserved - before).abs() < 1.0);
+        assert!(corner < centre);
+    }
+
+    #[test]
+    fn test_contours() {
+        let color = Pixel::rgb(255, 0, 0);
+        let mut pixels: Vec<Pixel> = (0..64 * 4).map(|i| Pixel::rgb((i % 64 * 4) as u8, (i % 64 * 4) as u8, (i % 64 * 4) as u8)).collect();
+        let mut image = Image::from_raw(&mut pixels[0], 64, 4);
+        image.filter(FilterType::Contours { levels: 3, color });
+
+        let lines: Vec<usize> = (0..64).filter(|&x| image.pixels[x] == color).collect();
+        assert_eq!(lines.len(), 3);
+        assert!((0..4).all(|y| lines.iter().all(|&x| image.pixels[y * 64 + x] == color)));
+    }
+
+    #[test]
+    fn test_radial_gradient() {
+        let mut pixels = [Pixel::rgb(180, 120, 60); 49];
+        let mut image = Image::from_raw(&mut pixels[0], 7, 7);
+        image.filter(FilterType::RadialGradient {
+            center: (3.0, 3.0),
+            inner: Pixel::rgb(255, 255, 255),
+            outer: Pixel::rgb(0, 0, 0),
+            radius: 3.0,
+            mode: BlendMode::Multiply,
+        });
+
+        assert_eq!(image.pixels[24], Pixel::rgb(180, 120, 60));
+        assert!(image.pixels[25].red < 180 && image.pixels[25].red > image.pixels[27].red);
+        assert_eq!(image.pixels[27], Pixel::rgb(0, 0, 0));
+        assert_eq!(image.pixels[0], Pixel::rgb(0, 0, 0));
+    }
+
+    #[test]
+    fn test_bit_depth() {
+        let original: Vec<Pixel> = (0..=255).map(|v| Pixel::rgb(v, 255 - v, v / 2)).collect();
+        let reduced = |bits: u8| {
+            let mut pixels = original.clone();
+            Image::from_raw(&mut pixels[0], 16, 16).filter(FilterType::BitDepth(bits));
+            pixels
+        };
+
+        assert_eq!(reduced(8), original);
+        assert!(reduced(1).iter().all(|p| [p.red, p.green, p.blue].iter().all(|&v| v == 0 || v == 255)));
+        assert_eq!(reduced(2)[100], Pixel::rgb(85, 170, 0));
+    }
+
+    #[test]
+    fn test_tilt_shift() {
+        let original: Vec<Pixel> =
+            (0..16 * 21).map(|i| if (i % 16 + i / 16) % 2 == 0 { Pixel::rgb(255, 255, 255) } else { Pixel::rgb(0, 0, 0) }).collect();
+        let mut pixels = original.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 16, 21);
+        image.filter(FilterType::TiltShift { center_y: 10, band: 4, max_sigma: 3.0 });
+
+        assert_eq!(&image.pixels[8 * 16..13 * 16], &original[8 * 16..13 * 16]);
+
+        for row in [0, 20] {
+            let middle = &image.pixels[row * 16 + 4..row * 16 + 12];
+            let contrast = middle.iter().map(|p| p.red).max().unwrap() - middle.iter().map(|p| p.red).min().unwrap();
+            assert!(contrast < 40);
+        }
+
+        let mut empty = Image { width: 0, height: 0, pixels: &mut [] };
+        empty.filter(FilterType::TiltShift { center_y: 0, band: 4, max_sigma: 3.0 });
+    }
+
+    #[test]
+    fn test_chroma_denoise() {
+        let mut pixels: Vec<Pixel> = (0..100).map(|i| if i % 10 < 5 { Pixel::rgb(60, 60, 60) } else { Pixel::rgb(200, 200, 200) }).collect();
+        for &i in &[12, 37, 66, 88] {
+            let Pixel { red, green, blue, .. } = pixels[i];
+            pixels[i] = Pixel::rgb(red.saturating_add(40), green, blue.saturating_sub(40));
+        }
+
+        let chroma = |p: &Pixel| {
+            let (_, cb, cr) = p.to_ycbcr();
+            (cb - 128.0).abs() + (cr - 128.0).abs()
+        };
+        let before: f64 = pixels.iter().map(chroma).sum();
+
+        let mut image = Image::from_raw(&mut pixels[0], 10, 10);
+        image.filter(FilterType::ChromaDenoise { radius: 1 });
+
+        let after: f64 = image.pixels.iter().map(chroma).sum();
+        assert!(after < before / 4.0);
+        for row in 0..10 {
+            assert!(image.pixels[row * 10 + 4].luminance() < 75);
+            assert!(image.pixels[row * 10 + 5].luminance() > 185);
+        }
+    }
+
+    #[test]
+    fn test_skin_smooth() {
+        let original: Vec<Pixel> = (0..144)
+            .map(|i| {
+                let bump = if (i % 12 + i / 12) % 2 == 0 { 10 } else { 0 };
+                if i % 12 < 6 {
+                    Pixel::rgb(214 + bump, 172, 140)
+                } else {
+                    Pixel::rgb(30 + bump * 3, 30 + bump * 3, 30 + bump * 3)
+                }
+            })
+            .collect();
+        let mut pixels = original.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 12, 12);
+        image.filter(FilterType::SkinSmooth { amount: 1.0 });
+
+        let spread = |pixels: &[Pixel], cols: std::ops::Range<usize>| {
+            let reds: Vec<u8> = (0..144).filter(|i| cols.contains(&(i % 12))).map(|i| pixels[i].red).collect();
+            reds.iter().max().unwrap() - reds.iter().min().unwrap()
+        };
+
+        assert!(spread(image.pixels, 1..5) < spread(&original, 1..5) / 2);
+        for i in (0..144).filter(|i| i % 12 >= 6) {
+            assert_eq!(image.pixels[i], original[i]);
+        }
+    }
+
+    #[test]
+    fn test_auto_gamma() {
+        let mut pixels: Vec<Pixel> = (0..64).map(|i| Pixel::rgb(20 + i as u8, 25 + i as u8, 30)).collect();
+        let mut image = Image::from_raw(&mut pixels[0], 8, 8);
+        let before = mean_luminance(&image);
+        image.filter(FilterType::AutoGamma);
+
+        let after = mean_luminance(&image);
+        assert!(after > before);
+        assert!((after - 128.0).abs() < 15.0);
+
+        let mut black = [Pixel::rgb(0, 0, 0); 4];
+        let mut image = Image::from_raw(&mut black[0], 2, 2);
+        image.filter(FilterType::AutoGamma);
+        assert_eq!(image.pixels, &[Pixel::rgb(0, 0, 0); 4]);
+    }
+
+    #[test]
+    fn test_radial_kaleidoscope() {
+        let mut pixels: Vec<Pixel> = (0..81).map(|i| Pixel::rgb((i * 3) as u8, (i * 7 % 256) as u8, (i % 9 * 20) as u8)).collect();
+        let mut image = Image::from_raw(&mut pixels[0], 9, 9);
+        image.filter(FilterType::RadialKaleidoscope { segments: 4 });
+
+        let at = |x: usize, y: usize| image.pixels[y * 9 + x];
+        for y in 0..9 {
+            for x in 0..9 {
+                assert_eq!(at(x, y), at(8 - x, y));
+                assert_eq!(at(x, y), at(x, 8 - y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsharp_luminance() {
+        let original: Vec<Pixel> = (0..80).map(|i| if i % 10 < 5 { Pixel::rgb(150, 110, 90) } else { Pixel::rgb(90, 70, 60) }).collect();
+        let mut pixels = original.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 10, 8);
+        image.filter(FilterType::UnsharpLuminance { radius: 2, sigma: 1.0, amount: 1.0 });
+
+        let contrast = |p: &[Pixel]| p[4].luminance_f64() - p[5].luminance_f64();
+        assert!(contrast(image.pixels) > contrast(&original) + 10.0);
+
+        for (after, before) in image.pixels.iter().zip(original.iter()) {
+            let (_, cb, cr) = after.to_ycbcr();
+            let (_, cb0, cr0) = before.to_ycbcr();
+            assert!((cb - cb0).abs() < 1.5 && (cr - cr0).abs() < 1.5);
+        }
+    }
+
+    #[test]
+    fn test_guided_filter() {
+        let original: Vec<Pixel> = (0..20 * 8)
+            .map(|i| {
+                let noise = if (i % 20 + i / 20) % 2 == 0 { 6 } else { 0 };
+                if i % 20 < 10 {
+                    Pixel::rgb(40 + noise, 40 + noise, 40 + noise)
+                } else {
+                    Pixel::rgb(220 - noise, 220 - noise, 220 - noise)
+                }
+            })
+            .collect();
+        let mut pixels = original.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 20, 8);
+        image.filter(FilterType::GuidedFilter { radius: 2, epsilon: 0.01 });
+
+        for row in 0..8 {
+            let line = &image.pixels[row * 20..row * 20 + 20];
+            assert!(line[1..5].iter().all(|p| (p.red as i16 - 43).abs() <= 1));
+            assert!(line[15..19].iter().all(|p| (p.red as i16 - 217).abs() <= 1));
+            assert!(line[9].red < 60 && line[10].red > 200);
+        }
+
+        let mut flat = [Pixel::rgb(90, 140, 200); 16];
+        let mut image = Image::from_raw(&mut flat[0], 4, 4);
+        image.filter(FilterType::GuidedFilter { radius: 1, epsilon: 0.01 });
+        assert_eq!(image.pixels, &[Pixel::rgb(90, 140, 200); 16]);
+
+        image.filter(FilterType::GuidedFilter { radius: 1, epsilon: 0.0 });
+        assert_eq!(image.pixels, &[Pixel::rgb(90, 140, 200); 16]);
+    }
+
+    #[test]
+    fn test_clarity() {
+        let original: Vec<Pixel> = (0..24 * 24)
+            .map(|i| {
+                let (x, y) = ((i % 24) as f64, (i / 24) as f64);
+                let v = 128.0 + 20.0 * (x / 3.0).sin() * (y / 4.0).cos();
+                Pixel::rgb(v as u8, v as u8, (v * 0.8) as u8)
+            })
+            .collect();
+        let stats = |pixels: &[Pixel]| {
+            let luma: Vec<f64> = pixels.iter().map(|p| p.luminance_f64()).collect();
+            let mean = luma.iter().sum::<f64>() / luma.len() as f64;
+            let variance = luma.iter().map(|l| (l - mean).powi(2)).sum::<f64>() / luma.len() as f64;
+            (mean, variance.sqrt())
+        };
+
+        let mut pixels = original.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 24, 24);
+        image.filter(FilterType::Clarity(1.0));
+
+        let (mean_before, spread_before) = stats(&original);
+        let (mean_after, spread_after) = stats(image.pixels);
+        assert!(spread_after > spread_before * 1.3);
+        assert!((mean_after - mean_before).abs() < 2.0);
+    }
+
+    #[test]
+    fn test_ascii_art() {
+        let mut pixels: Vec<Pixel> = (0..24 * 16).map(|i| if i % 24 < 12 { Pixel::rgb(0, 0, 0) } else { Pixel::rgb(255, 255, 255) }).collect();
+        let mut image = Image::from_raw(&mut pixels[0], 24, 16);
+        image.filter(FilterType::AsciiArt { cols: 4, ramp: "@%#*+=-:. ".to_string() });
+
+        assert_eq!((image.width, image.height), (24, 16));
+        assert_eq!(image.pixels.len(), 24 * 16);
+        assert!(image.pixels.iter().all(|p| *p == Pixel::rgb(0, 0, 0) || *p == Pixel::rgb(255, 255, 255)));
+
+        let ink = |cols: std::ops::Range<usize>| {
+            (0..24 * 16).filter(|i| cols.contains(&(i % 24)) && image.pixels[*i].red == 0).count()
+        };
+        assert!(ink(0..12) > 12 * 16 / 3);
+        assert_eq!(ink(12..24), 0);
+    }
+
+    #[test]
+    fn test_rank_equalize() {
+        let mut pixels: Vec<Pixel> = [3, 90, 7, 100, 12, 95, 40, 41, 42, 43, 44, 200, 201, 202, 9, 110]
+            .iter()
+            .map(|&v| Pixel::rgb(v, v, v))
+            .collect();
+        let mut image = Image::from_raw(&mut pixels[0], 4, 4);
+        image.filter(FilterType::RankEqualize);
+
+        let mut levels: Vec<u8> = image.pixels.iter().map(|p| p.luminance()).collect();
+        levels.sort();
+        assert_eq!(levels, (0..16).map(|r| r * 17).collect::<Vec<u8>>());
+        assert_eq!(image.pixels[0], Pixel::rgb(0, 0, 0));
+        assert_eq!(image.pixels[13], Pixel::rgb(255, 255, 255));
+
+        let mut pixels = [Pixel::rgb(200, 100, 50), Pixel::rgb(10, 10, 10), Pixel::rgb(120, 60, 30)];
+        let mut image = Image::from_raw(&mut pixels[0], 3, 1);
+        image.filter(FilterType::RankEqualize);
+        let Pixel { red, green, blue, .. } = image.pixels[2];
+        assert!(red > 120 && (red as i16 - green as i16 * 2).abs() <= 1 && (green as i16 - blue as i16 * 2).abs() <= 1);
     }
 }
@@ -1,5 +1,59 @@
 use std::slice;
-use pixel::Pixel;
+use pixel::{clamp_channel, GrayMode, Pixel};
+use convolution::{apply_convolution, gaussian_kernel, ConvolutionMatrix, SOBEL_X, SOBEL_Y};
+
+// How colour is stored relative to alpha in external RGBA buffers. Pixels
+// always hold straight alpha internally
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum AlphaMode {
+    Straight,
+    Premultiplied,
+}
+
+// How neighbourhoods treat coordinates that fall outside the image. Clamp
+// repeats the border pixel, Wrap tiles the image and Mirror reflects about
+// the border pixel without repeating it
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum EdgeMode {
+    Clamp,
+    Wrap,
+    Mirror,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum MessageError {
+    TooLarge { capacity: usize, needed: usize },
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum ParseError {
+    UnsupportedFormat,
+    InvalidHeader,
+    Truncated,
+}
+
+#[derive(Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum LimitError {
+    TooManyPixels { declared: Option<usize>, max: usize },
+    LengthMismatch { expected: usize, actual: usize },
+}
+
+// Pixels where at least one channel of a convolution fell below 0 or rose
+// above 255 before being clamped
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct ConvolutionReport {
+    pub clamped_low: usize,
+    pub clamped_high: usize,
+}
 
 pub struct Image<'a> {
     pub width: usize,
@@ -7,7 +61,9 @@ pub struct Image<'a> {
     pub pixels: &'a mut [Pixel],
 }
 
+#[cfg_attr(not(test), allow(dead_code))]
 impl<'a> Image<'a> {
+    #[allow(clippy::not_unsafe_ptr_arg_deref)]
     pub fn from_raw(ptr: *mut Pixel, width: usize, height: usize) -> Image<'a> {
         let num_pixels = width * height;
         let pixels = unsafe { slice::from_raw_parts_mut(ptr, num_pixels) };
@@ -24,11 +80,429 @@ impl<'a> Image<'a> {
             let (row, col) = self.index_to_row_col(i);
             if col >= self.width / 2 {
                 let target_idx = self.row_col_to_index(row, self.width - 1 - col);
-                let temp = self.pixels[i].clone();
-                self.pixels[i] = self.pixels[target_idx];
-                self.pixels[target_idx] = temp;
+                self.pixels.swap(i, target_idx);
+            }
+        }
+    }
+
+    // Copy of the rectangle at (x, y) with the given width and height
+
+    pub fn crop(&self, x: usize, y: usize, width: usize, height: usize) -> (Vec<Pixel>, usize, usize) {
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in y..y + height {
+            let start = self.row_col_to_index(row, x);
+            pixels.extend_from_slice(&self.pixels[start..start + width]);
+        }
+
+        (pixels, width, height)
+    }
+
+    // Repeat the image across a grid of repeat_x by repeat_y copies
+
+    pub fn tile(&self, repeat_x: usize, repeat_y: usize) -> (Vec<Pixel>, usize, usize) {
+        let width = self.width * repeat_x;
+        let height = self.height * repeat_y;
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            let start = self.row_col_to_index(row % self.height, 0);
+            for _ in 0..repeat_x {
+                pixels.extend_from_slice(&self.pixels[start..start + self.width]);
+            }
+        }
+
+        (pixels, width, height)
+    }
+
+    // Rotate by degrees about the centre, clockwise on screen since y grows
+    // downwards, into a canvas grown to fit the rotated corners. Uncovered
+    // pixels are filled with background and covered ones sampled bilinearly
+
+    pub fn rotate(&self, degrees: f64, background: Pixel) -> (Vec<Pixel>, usize, usize) {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let (w, h) = (self.width as f64, self.height as f64);
+        let width = ((w * cos.abs() + h * sin.abs()) - 1e-9).ceil().max(1.0) as usize;
+        let height = ((w * sin.abs() + h * cos.abs()) - 1e-9).ceil().max(1.0) as usize;
+
+        let (cx, cy) = ((w - 1.0) / 2.0, (h - 1.0) / 2.0);
+        let (ocx, ocy) = ((width as f64 - 1.0) / 2.0, (height as f64 - 1.0) / 2.0);
+
+        let mut pixels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for col in 0..width {
+                let (x, y) = (col as f64 - ocx, row as f64 - ocy);
+                let sx = x * cos + y * sin + cx;
+                let sy = -x * sin + y * cos + cy;
+
+                if sx < -0.5 || sy < -0.5 || sx > w - 0.5 || sy > h - 0.5 {
+                    pixels.push(background);
+                } else {
+                    pixels.push(self.sample_bilinear(sx, sy));
+                }
+            }
+        }
+
+        (pixels, width, height)
+    }
+
+    // Apply f in place to each pixel of the (x, y, width, height) rectangle,
+    // clipped to the image, without copying anything
+
+    pub fn map_region<F: Fn(&mut Pixel)>(&mut self, rect: (usize, usize, usize, usize), f: F) {
+        let (x, y, width, height) = rect;
+        if x >= self.width {
+            return;
+        }
+
+        let right = (x + width).min(self.width);
+        let bottom = (y + height).min(self.height);
+
+        for row in y..bottom {
+            let start = self.row_col_to_index(row, x);
+            let end = self.row_col_to_index(row, right);
+            for pixel in self.pixels[start..end].iter_mut() {
+                f(pixel);
+            }
+        }
+    }
+
+    // Largest centered square as an (x, y, width, height) box for crop
+
+    pub fn center_square(&self) -> (usize, usize, usize, usize) {
+        let size = self.width.min(self.height);
+        ((self.width - size) / 2, (self.height - size) / 2, size, size)
+    }
+
+    // Gaussian blur whose sigma varies per pixel with the luminance of the
+    // matching blur_map pixel, from sharp at 0 up to max_sigma at 255. Every
+    // pixel is a full 2D window over the original, so this costs roughly
+    // O(pixels * (6 * max_sigma)^2) and is much slower than a uniform blur.
+    // Kernels are cached per map level so at most 256 are ever built
+
+    pub fn variable_blur(&mut self, blur_map: &Image, max_sigma: f64) {
+        let original = self.pixels.to_vec();
+        let mut kernels: Vec<Option<Vec<f64>>> = vec![None; 256];
+
+        for i in 0..self.pixels.len() {
+            let level = blur_map.pixels[i].luminance() as usize;
+            let sigma = level as f64 / 255.0 * max_sigma;
+            if sigma < 0.01 {
+                continue;
+            }
+
+            let kernel = kernels[level].get_or_insert_with(|| gaussian_kernel(sigma));
+            let radius = (kernel.len() / 2) as isize;
+            let (row, col) = self.index_to_row_col(i);
+
+            let mut accum = [0.0; 3];
+            for (y, wy) in kernel.iter().enumerate() {
+                for (x, wx) in kernel.iter().enumerate() {
+                    let j = self.clamped_index(row as isize + y as isize - radius, col as isize + x as isize - radius);
+                    let weight = wy * wx;
+                    accum[0] += original[j].red as f64 * weight;
+                    accum[1] += original[j].green as f64 * weight;
+                    accum[2] += original[j].blue as f64 * weight;
+                }
+            }
+
+            self.pixels[i].set_rgb(
+                clamp_channel(accum[0]),
+                clamp_channel(accum[1]),
+                clamp_channel(accum[2]),
+            );
+        }
+    }
+
+    // Grayscale with the given formula, handing the mode back so callers can
+    // record or display which formula produced the result
+
+    pub fn grayscale_with(&mut self, mode: GrayMode) -> GrayMode {
+        let (wr, wg, wb) = mode.weights();
+        for pixel in self.pixels.iter_mut() {
+            let gray = pixel.weighted_gray(wr, wg, wb);
+            pixel.set_gray(gray);
+        }
+
+        mode
+    }
+
+    // Perceptual average hash: one bit per cell of an 8x8 grid of luminance
+    // means, set when the cell is brighter than the mean of all cells
+
+    pub fn average_hash(&self) -> u64 {
+        let mut cells = [0.0; 64];
+        let mut counts = [0usize; 64];
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            let (row, col) = self.index_to_row_col(i);
+            let cell = (row * 8 / self.height) * 8 + col * 8 / self.width;
+            cells[cell] += pixel.luminance_f64();
+            counts[cell] += 1;
+        }
+
+        for (cell, &count) in cells.iter_mut().zip(counts.iter()) {
+            if count > 0 {
+                *cell /= count as f64;
+            }
+        }
+
+        let mean = cells.iter().sum::<f64>() / 64.0;
+        cells
+            .iter()
+            .enumerate()
+            .fold(0, |hash, (bit, &cell)| if cell > mean { hash | 1 << bit } else { hash })
+    }
+
+    // Tint the image with a per-pixel scalar map, rescaled so its minimum is
+    // blue and its maximum red, blended over the image by alpha
+
+    pub fn overlay_scalar(&mut self, values: &[f64], alpha: f64) {
+        assert_eq!(values.len(), self.pixels.len());
+
+        let lo = values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let hi = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let range = if hi > lo { hi - lo } else { 1.0 };
+
+        for (pixel, &value) in self.pixels.iter_mut().zip(values.iter()) {
+            let t = (value - lo) / range;
+            let blend = |v: u8, tint: f64| clamp_channel(v as f64 * (1.0 - alpha) + tint * alpha);
+            let (red, green, blue) = (
+                blend(pixel.red, 255.0 * t),
+                blend(pixel.green, 0.0),
+                blend(pixel.blue, 255.0 * (1.0 - t)),
+            );
+            pixel.set_rgb(red, green, blue);
+        }
+    }
+
+    pub fn histogram(&self) -> [usize; 256] {
+        let mut histogram = [0; 256];
+        for pixel in self.pixels.iter() {
+            histogram[pixel.luminance() as usize] += 1;
+        }
+        histogram
+    }
+
+    // Luminance histogram drawn as white bars on black, each column covering
+    // an equal share of the 256 bins and scaled so the tallest is full height
+
+    pub fn render_histogram(&self, width: usize, height: usize) -> Vec<Pixel> {
+        let histogram = self.histogram();
+
+        let columns: Vec<usize> = (0..width)
+            .map(|x| {
+                let start = x * 256 / width;
+                let end = ((x + 1) * 256 / width).max(start + 1);
+                histogram[start..end].iter().sum()
+            })
+            .collect();
+        let tallest = columns.iter().cloned().max().unwrap_or(0).max(1);
+
+        let mut pixels = vec![Pixel::rgb(0, 0, 0); width * height];
+        for (x, &count) in columns.iter().enumerate() {
+            let bar = (count as f64 / tallest as f64 * height as f64).round() as usize;
+            for row in height - bar..height {
+                pixels[row * width + x] = Pixel::rgb(255, 255, 255);
+            }
+        }
+
+        pixels
+    }
+
+    // Same dimensions and every channel, alpha included, within tol
+
+    pub fn approx_eq(&self, other: &Image, tol: u8) -> bool {
+        let close = |a: u8, b: u8| (a as i16 - b as i16).abs() <= tol as i16;
+
+        self.width == other.width
+            && self.height == other.height
+            && self.pixels.iter().zip(other.pixels.iter()).all(|(a, b)| {
+                close(a.red, b.red) && close(a.green, b.green) && close(a.blue, b.blue) && close(a.alpha, b.alpha)
+            })
+    }
+
+    // Convolve with a kernel given as a function of the (dx, dy) offset over a
+    // (2 * radius + 1) square window. Weights are normalized by their total
+    // unless they sum to zero, as derivative kernels do. Edges are clamped.
+    // Whole-number weights up to i64::MAX are summed exactly in i128, which
+    // no window that fits in memory can overflow, so large integer kernels
+    // clamp the true weighted sum instead of wrapping or losing low bits
+
+    pub fn convolve_fn<F: Fn(i32, i32) -> f64>(&mut self, radius: usize, f: F) {
+        let r = radius as i32;
+        let mut weights = Vec::new();
+        for dy in -r..=r {
+            for dx in -r..=r {
+                weights.push((dx, dy, f(dx, dy)));
+            }
+        }
+
+        if weights.iter().all(|&(_, _, w)| w.fract() == 0.0 && w.abs() <= i64::MAX as f64) {
+            let weights: Vec<(i32, i32, i128)> = weights.iter().map(|&(dx, dy, w)| (dx, dy, w as i128)).collect();
+            let total = match weights.iter().map(|&(_, _, w)| w).sum() {
+                0 => 1,
+                total => total,
+            };
+
+            let response: Vec<[i128; 3]> = (0..self.pixels.len())
+                .map(|i| {
+                    let (row, col) = self.index_to_row_col(i);
+                    let mut accum = [0i128; 3];
+                    for &(dx, dy, w) in weights.iter() {
+                        let pix = self.pixels[self.clamped_index(row as isize + dy as isize, col as isize + dx as isize)];
+                        accum[0] += pix.red as i128 * w;
+                        accum[1] += pix.green as i128 * w;
+                        accum[2] += pix.blue as i128 * w;
+                    }
+                    accum
+                })
+                .collect();
+
+            let channel = |sum: i128| clamp_channel(sum as f64 / total as f64);
+            for (pixel, accum) in self.pixels.iter_mut().zip(response.iter()) {
+                pixel.set_rgb(channel(accum[0]), channel(accum[1]), channel(accum[2]));
+            }
+            return;
+        }
+
+        let mut total: f64 = weights.iter().map(|&(_, _, w)| w).sum();
+        if total.abs() < 1e-9 {
+            total = 1.0;
+        }
+
+        let response = self.convolve_fn_raw(radius, |dx, dy| f(dx, dy) / total);
+        for (pixel, accum) in self.pixels.iter_mut().zip(response.iter()) {
+            pixel.set_rgb(clamp_channel(accum[0]), clamp_channel(accum[1]), clamp_channel(accum[2]));
+        }
+    }
+
+    // Unnormalized, unclamped per-channel kernel response, for filters that
+    // need to offset or rescale the result before it becomes a pixel
+
+    pub fn convolve_fn_raw<F: Fn(i32, i32) -> f64>(&self, radius: usize, f: F) -> Vec<[f64; 3]> {
+        let r = radius as i32;
+        let mut weights = Vec::new();
+        for dy in -r..=r {
+            for dx in -r..=r {
+                weights.push((dx, dy, f(dx, dy)));
+            }
+        }
+
+        (0..self.pixels.len())
+            .map(|i| {
+                let (row, col) = self.index_to_row_col(i);
+                let mut accum = [0.0; 3];
+                for &(dx, dy, w) in weights.iter() {
+                    let pix = self.pixels[self.clamped_index(row as isize + dy as isize, col as isize + dx as isize)];
+                    accum[0] += pix.red as f64 * w;
+                    accum[1] += pix.green as f64 * w;
+                    accum[2] += pix.blue as f64 * w;
+                }
+                accum
+            })
+            .collect()
+    }
+
+    // Multiband blend of this image with other. White in the mask selects
+    // this image and black selects other. Each band of the two Laplacian
+    // pyramids is mixed by the matching level of the mask's gaussian
+    // pyramid, so coarse detail blends over a wide area and fine detail over
+    // a narrow one, hiding the seam
+
+    pub fn pyramid_blend(&self, other: &Image, mask: &Image, levels: usize) -> Vec<Pixel> {
+        assert!(
+            (other.width, other.height) == (self.width, self.height) && (mask.width, mask.height) == (self.width, self.height),
+            "pyramid_blend needs other and mask to match the image dimensions"
+        );
+
+        let to_values = |image: &Image| -> Vec<[f64; 3]> {
+            image.pixels.iter().map(|p| [p.red as f64, p.green as f64, p.blue as f64]).collect()
+        };
+        let weights: Vec<[f64; 3]> = mask
+            .pixels
+            .iter()
+            .map(|p| {
+                let w = p.luminance_f64() / 255.0;
+                [w, w, w]
+            })
+            .collect();
+
+        let a = laplacian_pyramid(to_values(self), self.width, self.height, levels);
+        let b = laplacian_pyramid(to_values(other), self.width, self.height, levels);
+        let m = gaussian_pyramid(weights, self.width, self.height, levels);
+
+        let blended: Vec<Level> = a
+            .iter()
+            .zip(b.iter())
+            .zip(m.iter())
+            .map(|((la, lb), lm)| {
+                let values = (0..la.values.len())
+                    .map(|i| {
+                        let (a, b, w) = (la.values[i], lb.values[i], lm.values[i]);
+                        [
+                            w[0] * a[0] + (1.0 - w[0]) * b[0],
+                            w[1] * a[1] + (1.0 - w[1]) * b[1],
+                            w[2] * a[2] + (1.0 - w[2]) * b[2],
+                        ]
+                    })
+                    .collect();
+                Level {
+                    values,
+                    width: la.width,
+                    height: la.height,
+                }
+            })
+            .collect();
+
+        let mut result = blended[blended.len() - 1].values.clone();
+        for pair in blended.windows(2).rev() {
+            let (fine, coarse) = (&pair[0], &pair[1]);
+            result = pyramid_up(&result, coarse.width, coarse.height, fine.width, fine.height);
+            for (v, detail) in result.iter_mut().zip(fine.values.iter()) {
+                for c in 0..3 {
+                    v[c] += detail[c];
+                }
             }
         }
+
+        result
+            .iter()
+            .map(|v| Pixel::rgb(clamp_channel(v[0]), clamp_channel(v[1]), clamp_channel(v[2])))
+            .collect()
+    }
+
+    // RGBA bytes in the requested alpha convention
+
+    pub fn to_rgba_bytes(&self, mode: AlphaMode) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for pixel in self.pixels.iter() {
+            let pixel = match mode {
+                AlphaMode::Straight => *pixel,
+                AlphaMode::Premultiplied => pixel.premultiplied(),
+            };
+            bytes.extend_from_slice(&[pixel.red, pixel.green, pixel.blue, pixel.alpha]);
+        }
+        bytes
+    }
+
+    // Red, green and blue planes as separate buffers, dropping alpha
+
+    pub fn split_channels(&self) -> (Vec<u8>, Vec<u8>, Vec<u8>) {
+        (
+            self.pixels.iter().map(|p| p.red).collect(),
+            self.pixels.iter().map(|p| p.green).collect(),
+            self.pixels.iter().map(|p| p.blue).collect(),
+        )
+    }
+
+    // Binary P6 PPM, dropping alpha
+
+    pub fn to_ppm(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.reserve(self.pixels.len() * 3);
+        for pixel in self.pixels.iter() {
+            bytes.extend_from_slice(&[pixel.red, pixel.green, pixel.blue]);
+        }
+        bytes
     }
 
     pub fn row_col_to_index(&self, row: usize, col: usize) -> usize {
@@ -39,120 +513,1773 @@ impl<'a> Image<'a> {
         (i / self.width, i % self.width)
     }
 
-    pub fn get_neighbour_colours(&self, i: usize) -> ([u8; 9], [u8; 9], [u8; 9]) {
-        let mut red = [0; 9];
-        let mut green = [0; 9];
-        let mut blue = [0; 9];
+    // Index of (row, col) with out of bounds coordinates clamped to the
+    // nearest edge pixel
 
-        let (row, col) = self.index_to_row_col(i);
+    pub fn clamped_index(&self, row: isize, col: isize) -> usize {
+        let row = row.clamp(0, self.height as isize - 1) as usize;
+        let col = col.clamp(0, self.width as isize - 1) as usize;
+        self.row_col_to_index(row, col)
+    }
 
-        let mut idx = 0;
+    pub fn edge_index(&self, row: isize, col: isize, mode: EdgeMode) -> usize {
+        let resolve = |v: isize, n: usize| -> usize {
+            let n = n as isize;
+            match mode {
+                EdgeMode::Clamp => v.clamp(0, n - 1) as usize,
+                EdgeMode::Wrap => v.rem_euclid(n) as usize,
+                EdgeMode::Mirror if n == 1 => 0,
+                EdgeMode::Mirror => {
+                    let period = 2 * (n - 1);
+                    let m = v.rem_euclid(period);
+                    (if m < n { m } else { period - m }) as usize
+                }
+            }
+        };
 
-        for i in row as isize - 1..row as isize + 2 {
-            for j in col as isize - 1..col as isize + 2 {
-                let pix = self.pixels[(self.width * (i as usize)) + (j as usize)];
-                red[idx] = pix.red;
-                green[idx] = pix.green;
-                blue[idx] = pix.blue;
-                idx += 1;
+        self.row_col_to_index(resolve(row, self.height), resolve(col, self.width))
+    }
+
+    // Each pixel index with its (2 * radius + 1)^2 neighbourhood in row-major
+    // order, so window filters can be written as a map
+
+    pub fn windows(&self, radius: usize, mode: EdgeMode) -> impl Iterator<Item = (usize, Vec<Pixel>)> + '_ {
+        let reach = radius as isize;
+        (0..self.pixels.len()).map(move |i| {
+            let (row, col) = self.index_to_row_col(i);
+            let mut window = Vec::with_capacity((2 * radius + 1) * (2 * radius + 1));
+            for dy in -reach..=reach {
+                for dx in -reach..=reach {
+                    window.push(self.pixels[self.edge_index(row as isize + dy, col as isize + dx, mode)]);
+                }
             }
+            (i, window)
+        })
+    }
+
+    // Bilinear interpolation at fractional (x, y), where integer coordinates
+    // land exactly on pixel centres and samples clamp to the border. An empty
+    // image has nothing to sample and gives transparent black
+
+    pub fn sample_bilinear(&self, x: f64, y: f64) -> Pixel {
+        if self.width == 0 || self.height == 0 {
+            return Pixel::rgba(0, 0, 0, 0);
         }
 
-        (red, green, blue)
+        let x = x.clamp(0.0, (self.width - 1) as f64);
+        let y = y.clamp(0.0, (self.height - 1) as f64);
+
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+
+        let p00 = self.pixels[self.row_col_to_index(y0, x0)];
+        let p10 = self.pixels[self.row_col_to_index(y0, x1)];
+        let p01 = self.pixels[self.row_col_to_index(y1, x0)];
+        let p11 = self.pixels[self.row_col_to_index(y1, x1)];
+
+        let lerp = |a: u8, b: u8, c: u8, d: u8| {
+            let top = a as f64 + (b as f64 - a as f64) * tx;
+            let bottom = c as f64 + (d as f64 - c as f64) * tx;
+            clamp_channel(top + (bottom - top) * ty)
+        };
+
+        Pixel::rgba(
+            lerp(p00.red, p10.red, p01.red, p11.red),
+            lerp(p00.green, p10.green, p01.green, p11.green),
+            lerp(p00.blue, p10.blue, p01.blue, p11.blue),
+            lerp(p00.alpha, p10.alpha, p01.alpha, p11.alpha),
+        )
     }
-}
 
-#[cfg(test)]
-mod tests {
+    pub fn gradient_magnitude(&self) -> Vec<f64> {
+        self.sobel_gradients()
+            .iter()
+            .map(|&(gx, gy)| (gx * gx + gy * gy).sqrt())
+            .collect()
+    }
 
-    use super::*;
+    // Gradient direction in radians as returned by atan2(gy, gx)
 
-    #[test]
-    fn test_from_raw() {
-        let mut pixels = [
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+    pub fn sobel_angles(&self) -> Vec<f64> {
+        self.sobel_gradients()
+            .iter()
+            .map(|&(gx, gy)| gy.atan2(gx))
+            .collect()
+    }
 
-        let image = Image::from_raw(&mut pixels[0], 2, 2);
+    // (magnitude, angle) per pixel from a single grayscale and gradient pass,
+    // for callers that need both
 
-        assert_eq!(image.width, 2);
-        assert_eq!(image.height, 2);
-        assert_eq!(image.pixels, pixels);
+    pub fn sobel_full(&self) -> Vec<(f64, f64)> {
+        self.sobel_gradients()
+            .iter()
+            .map(|&(gx, gy)| ((gx * gx + gy * gy).sqrt(), gy.atan2(gx)))
+            .collect()
     }
 
-    #[test]
-    fn test_flip_x() {
-        let mut pixels = [
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+    // Harris corner detector returning the (x, y) of every local maximum of
+    // det(M) - k * trace(M)^2 above threshold, where M is the structure tensor
+    // of the Sobel gradients summed over a gaussian window
 
-        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
-        image.flip_x();
+    pub fn harris_corners(&self, k: f64, threshold: f64) -> Vec<(usize, usize)> {
+        let gradients = self.sobel_gradients();
 
-        assert_eq!(image.pixels, [
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100,0, 0),
-        ]);
+        let xx: Vec<f64> = gradients.iter().map(|&(gx, _)| gx * gx).collect();
+        let yy: Vec<f64> = gradients.iter().map(|&(_, gy)| gy * gy).collect();
+        let xy: Vec<f64> = gradients.iter().map(|&(gx, gy)| gx * gy).collect();
+
+        let xx = self.smooth_values(&xx, 1.0);
+        let yy = self.smooth_values(&yy, 1.0);
+        let xy = self.smooth_values(&xy, 1.0);
+
+        let response: Vec<f64> = (0..self.pixels.len())
+            .map(|i| {
+                let det = xx[i] * yy[i] - xy[i] * xy[i];
+                let trace = xx[i] + yy[i];
+                det - k * trace * trace
+            })
+            .collect();
+
+        let mut corners = Vec::new();
+        for (i, &r) in response.iter().enumerate() {
+            if r <= threshold {
+                continue;
+            }
+
+            let (row, col) = self.index_to_row_col(i);
+            let mut is_max = true;
+            for dy in -1..2 {
+                for dx in -1..2 {
+                    let j = self.clamped_index(row as isize + dy, col as isize + dx);
+                    if j != i && (response[j] > r || (response[j] == r && j < i)) {
+                        is_max = false;
+                    }
+                }
+            }
+
+            if is_max {
+                corners.push((col, row));
+            }
+        }
+
+        corners
     }
 
-    #[test]
-    fn test_row_col_to_index() {
-        let mut pixels = [
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+    // Hides data in the least significant bit of each red, green and blue
+    // channel in turn, behind a 32 bit big-endian length. Alpha is left alone
+    // since browsers may premultiply it away
 
-        let image = Image::from_raw(&mut pixels[0], 2, 2);
+    pub fn embed_message(&mut self, data: &[u8]) -> Result<(), MessageError> {
+        let capacity = self.pixels.len() * 3 / 8;
+        let needed = data.len() + 4;
+        if needed > capacity {
+            return Err(MessageError::TooLarge { capacity, needed });
+        }
 
-        assert_eq!(image.row_col_to_index(1, 0), 2);
-        assert_eq!(image.row_col_to_index(1, 1), 3);
+        let length = (data.len() as u32).to_be_bytes();
+        let bits = length
+            .iter()
+            .chain(data.iter())
+            .flat_map(|&byte| (0..8).rev().map(move |i| byte >> i & 1));
+
+        let channels = self.pixels.iter_mut().flat_map(|p| vec![&mut p.red, &mut p.green, &mut p.blue]);
+        for (channel, bit) in channels.zip(bits) {
+            *channel = (*channel & !1) | bit;
+        }
+
+        Ok(())
     }
 
-    #[test]
-    fn test_index_to_row_col() {
-        let mut pixels = [
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-        ];
+    // Reads back a message written by embed_message. An image that was never
+    // embedded into decodes to a nonsense length, which is capped at capacity
 
-        let image = Image::from_raw(&mut pixels[0], 2, 2);
+    pub fn extract_message(&self) -> Vec<u8> {
+        let bits: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|p| vec![p.red & 1, p.green & 1, p.blue & 1])
+            .collect();
+        let bytes: Vec<u8> = bits.chunks(8).filter(|c| c.len() == 8).map(|c| c.iter().fold(0, |b, &bit| b << 1 | bit)).collect();
 
-        assert_eq!(image.index_to_row_col(2), (1, 0));
-        assert_eq!(image.index_to_row_col(3), (1, 1));
+        if bytes.len() < 4 {
+            return Vec::new();
+        }
+
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        bytes[4..].iter().take(length).cloned().collect()
     }
 
-    #[test]
-    fn test_get_neighbour_colours() {
-        let mut pixels = [
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-            Pixel::rgb(0, 0, 0),
-            Pixel::rgb(100, 0, 0),
-        ];
+    // Gradient magnitude summed by unsigned direction, with bins evenly
+    // covering 0..180 degrees starting from horizontal gradients
 
-        let image = Image::from_raw(&mut pixels[0], 3, 3);
+    pub fn orientation_histogram(&self, bins: usize) -> Vec<f64> {
+        let mut histogram = vec![0.0; bins];
+        if bins == 0 {
+            return histogram;
+        }
 
-        assert_eq!(image.get_neighbour_colours(4), (
-            [100, 0, 100, 0, 100, 0, 100, 0, 100],
-            [0, 0, 0, 0, 0, 0, 0, 0, 0],
-            [0, 0, 0, 0, 0, 0, 0, 0, 0],
-        ));
+        for (magnitude, angle) in self.sobel_full() {
+            let degrees = angle.to_degrees().rem_euclid(180.0);
+            let bin = ((degrees / 180.0 * bins as f64) as usize).min(bins - 1);
+            histogram[bin] += magnitude;
+        }
+
+        histogram
+    }
+
+    // Frames for a colour cycle animation, frame i rotated by i * 360 / count
+    // degrees of hue
+
+    pub fn hue_cycle_frames(&self, count: usize) -> Vec<Vec<Pixel>> {
+        (0..count)
+            .map(|i| {
+                let degrees = i as f64 * 360.0 / count as f64;
+                self.pixels.iter().map(|p| p.hue_rotated(degrees)).collect()
+            })
+            .collect()
+    }
+
+    // Surrounds the image with a frame border pixels wide, plus bottom_extra
+    // more along the bottom for a caption strip
+
+    pub fn polaroid(&self, border: usize, bottom_extra: usize, frame: Pixel) -> (Vec<Pixel>, usize, usize) {
+        let width = self.width + border * 2;
+        let height = self.height + border * 2 + bottom_extra;
+
+        let mut pixels = vec![frame; width * height];
+        for row in 0..self.height {
+            let start = self.row_col_to_index(row, 0);
+            let target = (row + border) * width + border;
+            pixels[target..target + self.width].copy_from_slice(&self.pixels[start..start + self.width]);
+        }
+
+        (pixels, width, height)
+    }
+
+    // Adds color with a smooth (1 - d / radius)^2 falloff around (x, y).
+    // Pixels at or beyond radius are untouched
+
+    pub fn add_flare(&mut self, x: usize, y: usize, radius: f64, color: Pixel) {
+        for i in 0..self.pixels.len() {
+            let (row, col) = self.index_to_row_col(i);
+            let (dx, dy) = (col as f64 - x as f64, row as f64 - y as f64);
+            let distance = (dx * dx + dy * dy).sqrt();
+            if distance >= radius {
+                continue;
+            }
+
+            let falloff = (1.0 - distance / radius).powi(2);
+            let pixel = &mut self.pixels[i];
+            pixel.set_rgb(
+                clamp_channel(pixel.red as f64 + color.red as f64 * falloff),
+                clamp_channel(pixel.green as f64 + color.green as f64 * falloff),
+                clamp_channel(pixel.blue as f64 + color.blue as f64 * falloff),
+            );
+        }
+    }
+
+    // Luminance variance over the window of the given radius around each pixel,
+    // clipped to the image, from integral images of the values and their
+    // squares. Variance tops out at 127.5^2, so it's divided by 64 to fit a u8
+
+    pub fn local_variance(&self, radius: usize) -> Vec<u8> {
+        let stride = self.width + 1;
+        let mut sums = vec![0.0; stride * (self.height + 1)];
+        let mut squares = vec![0.0; stride * (self.height + 1)];
+
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let l = self.pixels[self.row_col_to_index(row, col)].luminance() as f64;
+                let i = (row + 1) * stride + col + 1;
+                sums[i] = l + sums[i - 1] + sums[i - stride] - sums[i - stride - 1];
+                squares[i] = l * l + squares[i - 1] + squares[i - stride] - squares[i - stride - 1];
+            }
+        }
+
+        let area = |table: &[f64], top: usize, left: usize, bottom: usize, right: usize| {
+            table[bottom * stride + right] - table[top * stride + right] - table[bottom * stride + left] + table[top * stride + left]
+        };
+
+        (0..self.pixels.len())
+            .map(|i| {
+                let (row, col) = self.index_to_row_col(i);
+                let (top, left) = (row.saturating_sub(radius), col.saturating_sub(radius));
+                let (bottom, right) = ((row + radius + 1).min(self.height), (col + radius + 1).min(self.width));
+
+                let n = ((bottom - top) * (right - left)) as f64;
+                let mean = area(&sums, top, left, bottom, right) / n;
+                let variance = area(&squares, top, left, bottom, right) / n - mean * mean;
+                clamp_channel(variance.max(0.0) / 64.0)
+            })
+            .collect()
+    }
+
+    // Approximate euclidean distance from each pixel to the nearest non-black
+    // pixel, by a forward and a backward chamfer pass with weights 1 and
+    // sqrt(2). Everything is infinite if there is no foreground at all
+
+    pub fn distance_transform(&self) -> Vec<f64> {
+        let mut distances: Vec<f64> = self
+            .pixels
+            .iter()
+            .map(|p| if p.red == 0 && p.green == 0 && p.blue == 0 { f64::INFINITY } else { 0.0 })
+            .collect();
+
+        let diagonal = 2f64.sqrt();
+        let forward = [(-1, -1, diagonal), (-1, 0, 1.0), (-1, 1, diagonal), (0, -1, 1.0)];
+        let backward = [(1, 1, diagonal), (1, 0, 1.0), (1, -1, diagonal), (0, 1, 1.0)];
+
+        let (width, height) = (self.width as isize, self.height as isize);
+        let relax = |distances: &mut [f64], row: isize, col: isize, offsets: &[(isize, isize, f64)]| {
+            let i = (row * width + col) as usize;
+            for &(dr, dc, cost) in offsets {
+                let (r, c) = (row + dr, col + dc);
+                if r >= 0 && r < height && c >= 0 && c < width {
+                    let candidate = distances[(r * width + c) as usize] + cost;
+                    if candidate < distances[i] {
+                        distances[i] = candidate;
+                    }
+                }
+            }
+        };
+
+        for row in 0..height {
+            for col in 0..width {
+                relax(&mut distances, row, col, &forward);
+            }
+        }
+
+        for row in (0..height).rev() {
+            for col in (0..width).rev() {
+                relax(&mut distances, row, col, &backward);
+            }
+        }
+
+        distances
+    }
+
+    // Composites the image over a blurred, offset copy of its alpha silhouette
+    // tinted with color. The canvas grows by the 3 sigma blur reach on every
+    // side plus the offset on the side it points to, and the original sits
+    // at (left, top) of the result
+
+    pub fn drop_shadow(&self, offset: (i32, i32), blur: f64, color: Pixel) -> (Vec<Pixel>, usize, usize) {
+        let pad = (blur.max(0.0) * 3.0).ceil() as usize;
+        let (ox, oy) = (offset.0 as isize, offset.1 as isize);
+        let left = pad + (-ox).max(0) as usize;
+        let top = pad + (-oy).max(0) as usize;
+        let width = self.width + left + pad + ox.max(0) as usize;
+        let height = self.height + top + pad + oy.max(0) as usize;
+
+        let mut canvas = OwnedImage::new(vec![Pixel::rgba(0, 0, 0, 0); width * height], width, height);
+        let mut silhouette = vec![0.0; width * height];
+        for (i, pixel) in self.pixels.iter().enumerate() {
+            let (row, col) = self.index_to_row_col(i);
+            let (row, col) = (row + top, col + left);
+            canvas.pixels[row * width + col] = *pixel;
+
+            let shadow_row = (row as isize + oy) as usize;
+            let shadow_col = (col as isize + ox) as usize;
+            silhouette[shadow_row * width + shadow_col] = pixel.alpha as f64;
+        }
+
+        if blur > 0.0 {
+            silhouette = canvas.as_image().smooth_values(&silhouette, blur);
+        }
+
+        let pixels = canvas
+            .pixels
+            .iter()
+            .zip(silhouette.iter())
+            .map(|(pixel, &a)| {
+                let alpha = clamp_channel(a * color.alpha as f64 / 255.0);
+                pixel.over(&Pixel::rgba(color.red, color.green, color.blue, alpha))
+            })
+            .collect();
+
+        (pixels, width, height)
+    }
+
+    // Skew in degrees of the dominant straight lines, within -45..45, positive
+    // when lines fall towards the right (y grows downwards). Strong edge
+    // pixels are projected across each candidate angle in quarter degree
+    // steps, and the angle whose projection piles them into the fewest, fullest
+    // rows wins
+
+    pub fn detect_skew(&self) -> f64 {
+        let gradients = self.gradient_magnitude();
+        let strongest = gradients.iter().cloned().fold(0.0, f64::max);
+        if strongest == 0.0 {
+            return 0.0;
+        }
+
+        let edges: Vec<(f64, f64, f64)> = gradients
+            .iter()
+            .enumerate()
+            .filter(|&(_, &m)| m > strongest * 0.25)
+            .map(|(i, &m)| {
+                let (row, col) = self.index_to_row_col(i);
+                (col as f64, row as f64, m)
+            })
+            .collect();
+
+        let reach = (self.width + self.height) as f64;
+        let mut best = (0.0, f64::MIN);
+        for step in -180..=180 {
+            let angle = step as f64 / 4.0;
+            let (sin, cos) = angle.to_radians().sin_cos();
+
+            let mut profile = vec![0.0; 2 * (self.width + self.height) + 1];
+            for &(x, y, m) in edges.iter() {
+                let offset = y * cos - x * sin + reach;
+                profile[offset as usize] += m;
+            }
+
+            let score: f64 = profile.iter().map(|v| v * v).sum();
+            if score > best.1 {
+                best = (angle, score);
+            }
+        }
+
+        best.0
+    }
+
+    // Straighten the image by rotating away the skew found by detect_skew
+
+    pub fn auto_deskew(&self, background: Pixel) -> (Vec<Pixel>, usize, usize) {
+        self.rotate(-self.detect_skew(), background)
+    }
+
+    // White where the pixel's chroma falls in the skin tone box of Chai and
+    // Ngan (77 <= Cb <= 127, 133 <= Cr <= 173), black elsewhere
+
+    pub fn skin_mask(&self) -> Vec<Pixel> {
+        self.pixels
+            .iter()
+            .map(|p| {
+                let (_, cb, cr) = p.to_ycbcr();
+                if (77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr) {
+                    Pixel::rgb(255, 255, 255)
+                } else {
+                    Pixel::rgb(0, 0, 0)
+                }
+            })
+            .collect()
+    }
+
+    // Opaque grayscale view of the alpha channel
+
+    pub fn alpha_as_gray(&self) -> Vec<Pixel> {
+        self.pixels.iter().map(|p| Pixel::rgb(p.alpha, p.alpha, p.alpha)).collect()
+    }
+
+    // Composite over a checkerboard of tile sized squares, light in the top
+    // left corner, the way editors preview transparency
+
+    pub fn over_checkerboard(&self, tile: usize, light: Pixel, dark: Pixel) -> Vec<Pixel> {
+        let tile = tile.max(1);
+        self.pixels
+            .iter()
+            .enumerate()
+            .map(|(i, pixel)| {
+                let (row, col) = self.index_to_row_col(i);
+                let background = if (row / tile + col / tile) % 2 == 1 { dark } else { light };
+                pixel.over(&background)
+            })
+            .collect()
+    }
+
+    // Shannon entropy in bits of the luminance histogram, from 0 for a single
+    // level up to 8 when all 256 levels are equally common
+
+    pub fn entropy(&self) -> f64 {
+        let total = self.pixels.len() as f64;
+        self.histogram()
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+
+    // Same as the Convolution filter, skipping the outer border, but counting
+    // how many pixels had a channel clamped at either end
+
+    pub fn convolve_reporting(&mut self, kernel: ConvolutionMatrix) -> ConvolutionReport {
+        let mut pixels_copy = self.pixels.to_vec();
+        let original = Image {
+            width: self.width,
+            height: self.height,
+            pixels: &mut pixels_copy[..],
+        };
+
+        let raw = |values: &[u8; 9]| -> f64 {
+            values.iter().zip(kernel.iter().flat_map(|row| row.iter())).map(|(&v, &w)| v as f64 * w as f64).sum()
+        };
+
+        let mut report = ConvolutionReport { clamped_low: 0, clamped_high: 0 };
+        for i in 0..self.pixels.len() {
+            let (row, col) = self.index_to_row_col(i);
+            if row == 0 || col == 0 || row + 1 >= self.height || col + 1 >= self.width {
+                continue;
+            }
+
+            let (red, green, blue) = original.get_neighbour_colours(i);
+            let sums = [raw(&red), raw(&green), raw(&blue)];
+            if sums.iter().any(|&v| v < 0.0) {
+                report.clamped_low += 1;
+            }
+            if sums.iter().any(|&v| v > 255.0) {
+                report.clamped_high += 1;
+            }
+
+            self.pixels[i].set_rgb(
+                apply_convolution(red, kernel),
+                apply_convolution(green, kernel),
+                apply_convolution(blue, kernel),
+            );
+        }
+
+        report
+    }
+
+    // Runs f over a copy of each tile x tile block grown by halo pixels of
+    // context on every side (clipped to the image), passing the core's (x, y)
+    // origin, and writes only the core back. Each tile sees unmodified
+    // neighbours, since results land in self only after f returns
+
+    pub fn process_tiles<F: FnMut(&mut Image, usize, usize)>(&mut self, tile: usize, halo: usize, mut f: F) {
+        if tile == 0 {
+            return;
+        }
+
+        let original = self.pixels.to_vec();
+
+        for y in (0..self.height).step_by(tile) {
+            for x in (0..self.width).step_by(tile) {
+                let (right, bottom) = ((x + tile).min(self.width), (y + tile).min(self.height));
+                let (left, top) = (x.saturating_sub(halo), y.saturating_sub(halo));
+                let (padded_right, padded_bottom) = ((right + halo).min(self.width), (bottom + halo).min(self.height));
+
+                let width = padded_right - left;
+                let mut pixels = Vec::with_capacity(width * (padded_bottom - top));
+                for row in top..padded_bottom {
+                    pixels.extend_from_slice(&original[row * self.width + left..row * self.width + padded_right]);
+                }
+
+                let mut block = OwnedImage::new(pixels, width, padded_bottom - top);
+                f(&mut block.as_image(), x, y);
+
+                for row in y..bottom {
+                    let start = (row - top) * width + (x - left);
+                    let target = self.row_col_to_index(row, x);
+                    self.pixels[target..target + right - x].copy_from_slice(&block.pixels[start..start + right - x]);
+                }
+            }
+        }
+    }
+
+    // Separable gaussian smoothing of a per-pixel scalar map
+
+    pub fn smooth_values(&self, values: &[f64], sigma: f64) -> Vec<f64> {
+        let kernel = gaussian_kernel(sigma);
+        let radius = (kernel.len() / 2) as isize;
+
+        let horizontal: Vec<f64> = (0..values.len())
+            .map(|i| {
+                let (row, col) = self.index_to_row_col(i);
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| values[self.clamped_index(row as isize, col as isize + k as isize - radius)] * w)
+                    .sum()
+            })
+            .collect();
+
+        (0..values.len())
+            .map(|i| {
+                let (row, col) = self.index_to_row_col(i);
+                kernel
+                    .iter()
+                    .enumerate()
+                    .map(|(k, w)| horizontal[self.clamped_index(row as isize + k as isize - radius, col as isize)] * w)
+                    .sum()
+            })
+            .collect()
+    }
+
+    // Raw (gx, gy) Sobel response of the luminance at each pixel
+
+    pub fn sobel_gradients(&self) -> Vec<(f64, f64)> {
+        let luma: Vec<f64> = self.pixels.iter().map(|p| p.luminance() as f64).collect();
+
+        (0..self.pixels.len())
+            .map(|i| {
+                let (row, col) = self.index_to_row_col(i);
+                let (mut gx, mut gy) = (0.0, 0.0);
+
+                for r in 0..3 {
+                    for c in 0..3 {
+                        let j = self.clamped_index(row as isize + r as isize - 1, col as isize + c as isize - 1);
+                        gx += luma[j] * SOBEL_X[r][c] as f64;
+                        gy += luma[j] * SOBEL_Y[r][c] as f64;
+                    }
+                }
+
+                (gx, gy)
+            })
+            .collect()
+    }
+
+    pub fn get_neighbour_colours(&self, i: usize) -> ([u8; 9], [u8; 9], [u8; 9]) {
+        let mut red = [0; 9];
+        let mut green = [0; 9];
+        let mut blue = [0; 9];
+
+        let (row, col) = self.index_to_row_col(i);
+
+        let mut idx = 0;
+
+        for i in row as isize - 1..row as isize + 2 {
+            for j in col as isize - 1..col as isize + 2 {
+                let pix = self.pixels[(self.width * (i as usize)) + (j as usize)];
+                red[idx] = pix.red;
+                green[idx] = pix.green;
+                blue[idx] = pix.blue;
+                idx += 1;
+            }
+        }
+
+        (red, green, blue)
+    }
+}
+
+// An image that owns its pixel buffer, for results and generated images
+// that don't live in memory handed to us by JS
+
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct OwnedImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Pixel>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl OwnedImage {
+    pub fn new(pixels: Vec<Pixel>, width: usize, height: usize) -> OwnedImage {
+        OwnedImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    // Load RGBA bytes, unpremultiplying them first if they are premultiplied
+
+    pub fn from_rgba_bytes(bytes: &[u8], width: usize, height: usize, mode: AlphaMode) -> OwnedImage {
+        assert_eq!(bytes.len(), width * height * 4);
+
+        let pixels = bytes
+            .chunks(4)
+            .map(|c| {
+                let pixel = Pixel::rgba(c[0], c[1], c[2], c[3]);
+                match mode {
+                    AlphaMode::Straight => pixel,
+                    AlphaMode::Premultiplied => pixel.unpremultiplied(),
+                }
+            })
+            .collect();
+
+        OwnedImage::new(pixels, width, height)
+    }
+
+    // Binary P5 (grayscale) or P6 (RGB) netpbm, with comments allowed in the
+    // header. Samples are one byte below a maxval of 256 and two big-endian
+    // bytes otherwise, and either way are rescaled from maxval to 8 bits.
+    // Dimensions too large to size the sample data count as an invalid header
+
+    pub fn from_ppm(bytes: &[u8]) -> Result<OwnedImage, ParseError> {
+        let channels = match bytes.get(..2) {
+            Some(b"P5") => 1,
+            Some(b"P6") => 3,
+            _ => return Err(ParseError::UnsupportedFormat),
+        };
+
+        let mut pos = 2;
+        let mut fields = [0usize; 3];
+        for field in fields.iter_mut() {
+            loop {
+                match bytes.get(pos) {
+                    Some(b'#') => {
+                        while bytes.get(pos).is_some_and(|&b| b != b'\n') {
+                            pos += 1;
+                        }
+                    }
+                    Some(b) if b.is_ascii_whitespace() => pos += 1,
+                    Some(_) => break,
+                    None => return Err(ParseError::Truncated),
+                }
+            }
+
+            let start = pos;
+            while bytes.get(pos).is_some_and(|b| b.is_ascii_digit()) {
+                pos += 1;
+            }
+            *field = std::str::from_utf8(&bytes[start..pos])
+                .ok()
+                .and_then(|digits| digits.parse().ok())
+                .ok_or(ParseError::InvalidHeader)?;
+        }
+
+        let [width, height, maxval] = fields;
+        if maxval == 0 || maxval > 65535 || !bytes.get(pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            return Err(ParseError::InvalidHeader);
+        }
+        pos += 1;
+
+        let sample_size = if maxval < 256 { 1 } else { 2 };
+        let count = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(channels))
+            .ok_or(ParseError::InvalidHeader)?;
+        let needed = count.checked_mul(sample_size).ok_or(ParseError::InvalidHeader)?;
+
+        let data = &bytes[pos..];
+        if data.len() < needed {
+            return Err(ParseError::Truncated);
+        }
+
+        let samples: Vec<u8> = data
+            .chunks(sample_size)
+            .take(count)
+            .map(|c| {
+                let value = c.iter().fold(0usize, |v, &b| v << 8 | b as usize);
+                clamp_channel(value as f64 * 255.0 / maxval as f64)
+            })
+            .collect();
+
+        let pixels = samples
+            .chunks(channels)
+            .map(|c| if channels == 1 { Pixel::rgb(c[0], c[0], c[0]) } else { Pixel::rgb(c[0], c[1], c[2]) })
+            .collect();
+
+        Ok(OwnedImage::new(pixels, width, height))
+    }
+
+    // Straight alpha RGBA load for untrusted sizes, checking the declared
+    // dimensions against max_pixels before anything is allocated. A declared
+    // size too large to even multiply out is reported with no pixel count, and
+    // one whose byte length overflows counts as too many pixels as well
+
+    pub fn load_rgba_limited(bytes: &[u8], width: usize, height: usize, max_pixels: usize) -> Result<OwnedImage, LimitError> {
+        let declared = width.checked_mul(height);
+        let expected = match declared {
+            Some(pixels) if pixels <= max_pixels => pixels.checked_mul(4),
+            _ => None,
+        }
+        .ok_or(LimitError::TooManyPixels { declared, max: max_pixels })?;
+
+        if bytes.len() != expected {
+            return Err(LimitError::LengthMismatch { expected, actual: bytes.len() });
+        }
+
+        Ok(OwnedImage::from_rgba_bytes(bytes, width, height, AlphaMode::Straight))
+    }
+
+    // Opaque image from separate channel planes, as made by split_channels
+
+    pub fn merge_channels(red: &[u8], green: &[u8], blue: &[u8], width: usize, height: usize) -> OwnedImage {
+        assert!(red.len() == width * height && green.len() == red.len() && blue.len() == red.len());
+
+        let pixels = red
+            .iter()
+            .zip(green.iter())
+            .zip(blue.iter())
+            .map(|((&r, &g), &b)| Pixel::rgb(r, g, b))
+            .collect();
+
+        OwnedImage::new(pixels, width, height)
+    }
+
+    // Build an image by calling f(x, y) for every pixel
+
+    pub fn from_fn<F: Fn(usize, usize) -> Pixel>(width: usize, height: usize, f: F) -> OwnedImage {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.push(f(x, y));
+            }
+        }
+
+        OwnedImage::new(pixels, width, height)
+    }
+
+    pub fn as_image(&mut self) -> Image<'_> {
+        Image {
+            width: self.width,
+            height: self.height,
+            pixels: &mut self.pixels[..],
+        }
+    }
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+struct Level {
+    values: Vec<[f64; 3]>,
+    width: usize,
+    height: usize,
+}
+
+// Successively blurred and halved copies, stopping early once both
+// dimensions are down to a single pixel
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn gaussian_pyramid(values: Vec<[f64; 3]>, width: usize, height: usize, levels: usize) -> Vec<Level> {
+    let mut pyramid = vec![Level { values, width, height }];
+
+    while pyramid.len() < levels.max(1) {
+        let (values, width, height) = {
+            let last = &pyramid[pyramid.len() - 1];
+            if last.width < 2 && last.height < 2 {
+                break;
+            }
+            pyramid_down(&last.values, last.width, last.height)
+        };
+        pyramid.push(Level { values, width, height });
+    }
+
+    pyramid
+}
+
+// Each level minus the upsampled next level, keeping the coarsest gaussian
+// level as the last entry
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn laplacian_pyramid(values: Vec<[f64; 3]>, width: usize, height: usize, levels: usize) -> Vec<Level> {
+    let gaussian = gaussian_pyramid(values, width, height, levels);
+
+    let mut pyramid: Vec<Level> = gaussian
+        .windows(2)
+        .map(|pair| {
+            let (fine, coarse) = (&pair[0], &pair[1]);
+            let up = pyramid_up(&coarse.values, coarse.width, coarse.height, fine.width, fine.height);
+            let values = fine
+                .values
+                .iter()
+                .zip(up.iter())
+                .map(|(f, u)| [f[0] - u[0], f[1] - u[1], f[2] - u[2]])
+                .collect();
+            Level {
+                values,
+                width: fine.width,
+                height: fine.height,
+            }
+        })
+        .collect();
+
+    let top = &gaussian[gaussian.len() - 1];
+    pyramid.push(Level {
+        values: top.values.clone(),
+        width: top.width,
+        height: top.height,
+    });
+
+    pyramid
+}
+
+// Each coarse pixel is a [1, 3, 3, 1] binomial average centred on the 2x2
+// block it replaces, keeping the pyramid centre aligned
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn pyramid_down(values: &[[f64; 3]], width: usize, height: usize) -> (Vec<[f64; 3]>, usize, usize) {
+    let taps = [1.0 / 8.0, 3.0 / 8.0, 3.0 / 8.0, 1.0 / 8.0];
+    let at = |row: isize, col: isize| {
+        let row = row.clamp(0, height as isize - 1) as usize;
+        let col = col.clamp(0, width as isize - 1) as usize;
+        row * width + col
+    };
+
+    let (down_w, down_h) = (width.div_ceil(2), height.div_ceil(2));
+    let mut down = Vec::with_capacity(down_w * down_h);
+    for row in 0..down_h {
+        for col in 0..down_w {
+            let mut v = [0.0; 3];
+            for (i, wy) in taps.iter().enumerate() {
+                for (j, wx) in taps.iter().enumerate() {
+                    let src = values[at((row * 2 + i) as isize - 1, (col * 2 + j) as isize - 1)];
+                    for (channel, value) in v.iter_mut().enumerate() {
+                        *value += src[channel] * wy * wx;
+                    }
+                }
+            }
+            down.push(v);
+        }
+    }
+
+    (down, down_w, down_h)
+}
+
+// Bilinear resample of a level back up to the size of the level below it
+
+#[cfg_attr(not(test), allow(dead_code))]
+fn pyramid_up(values: &[[f64; 3]], width: usize, height: usize, up_w: usize, up_h: usize) -> Vec<[f64; 3]> {
+    let mut up = Vec::with_capacity(up_w * up_h);
+    for row in 0..up_h {
+        for col in 0..up_w {
+            let y = ((row as f64 + 0.5) / 2.0 - 0.5).clamp(0.0, (height - 1) as f64);
+            let x = ((col as f64 + 0.5) / 2.0 - 0.5).clamp(0.0, (width - 1) as f64);
+            let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+            let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+            let (tx, ty) = (x - x0 as f64, y - y0 as f64);
+
+            let mut v = [0.0; 3];
+            for c in 0..3 {
+                let top = values[y0 * width + x0][c] * (1.0 - tx) + values[y0 * width + x1][c] * tx;
+                let bottom = values[y1 * width + x0][c] * (1.0 - tx) + values[y1 * width + x1][c] * tx;
+                v[c] = top * (1.0 - ty) + bottom * ty;
+            }
+            up.push(v);
+        }
+    }
+
+    up
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use filter::{FilterType, ImageFilterExt};
+    use convolution::BLUR;
+
+    #[test]
+    fn test_from_raw() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+
+        assert_eq!(image.width, 2);
+        assert_eq!(image.height, 2);
+        assert_eq!(image.pixels, pixels);
+    }
+
+    #[test]
+    fn test_flip_x() {
+        let mut pixels = [
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 2);
+        image.flip_x();
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100,0, 0),
+        ]);
+    }
+
+    #[test]
+    fn test_row_col_to_index() {
+        let mut pixels = [
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+
+        assert_eq!(image.row_col_to_index(1, 0), 2);
+        assert_eq!(image.row_col_to_index(1, 1), 3);
+    }
+
+    #[test]
+    fn test_index_to_row_col() {
+        let mut pixels = [
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+
+        assert_eq!(image.index_to_row_col(2), (1, 0));
+        assert_eq!(image.index_to_row_col(3), (1, 1));
+    }
+
+    #[test]
+    fn test_get_neighbour_colours() {
+        let mut pixels = [
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(100, 0, 0),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 3, 3);
+
+        assert_eq!(image.get_neighbour_colours(4), (
+            [100, 0, 100, 0, 100, 0, 100, 0, 100],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [0, 0, 0, 0, 0, 0, 0, 0, 0],
+        ));
+    }
+
+    #[test]
+    fn test_clamped_index() {
+        let mut pixels = [Pixel::rgb(0, 0, 0); 6];
+        let image = Image::from_raw(&mut pixels[0], 3, 2);
+
+        assert_eq!(image.clamped_index(-1, -1), 0);
+        assert_eq!(image.clamped_index(1, 1), 4);
+        assert_eq!(image.clamped_index(5, 5), 5);
+    }
+
+    #[test]
+    fn test_gradient_magnitude() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 3, 3);
+        let magnitude = image.gradient_magnitude();
+
+        assert_eq!(magnitude[0], 0.0);
+        assert_eq!(magnitude[4], 1020.0);
+    }
+
+    #[test]
+    fn test_crop() {
+        let mut pixels = [
+            Pixel::rgb(1, 0, 0),
+            Pixel::rgb(2, 0, 0),
+            Pixel::rgb(3, 0, 0),
+            Pixel::rgb(4, 0, 0),
+            Pixel::rgb(5, 0, 0),
+            Pixel::rgb(6, 0, 0),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 3, 2);
+
+        assert_eq!(image.crop(1, 0, 2, 2), (vec![
+            Pixel::rgb(2, 0, 0),
+            Pixel::rgb(3, 0, 0),
+            Pixel::rgb(5, 0, 0),
+            Pixel::rgb(6, 0, 0),
+        ], 2, 2));
+    }
+
+    #[test]
+    fn test_center_square() {
+        let mut pixels = vec![Pixel::rgb(0, 0, 0); 100 * 60];
+        let image = Image::from_raw(&mut pixels[0], 100, 60);
+        assert_eq!(image.center_square(), (20, 0, 60, 60));
+
+        let mut pixels = [Pixel::rgb(0, 0, 0); 16];
+        let image = Image::from_raw(&mut pixels[0], 4, 4);
+        assert_eq!(image.center_square(), (0, 0, 4, 4));
+    }
+
+    #[test]
+    fn test_tile() {
+        let mut pixels = [
+            Pixel::rgb(1, 0, 0),
+            Pixel::rgb(2, 0, 0),
+            Pixel::rgb(3, 0, 0),
+            Pixel::rgb(4, 0, 0),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+        let (mut tiled, width, height) = image.tile(2, 2);
+
+        assert_eq!((width, height), (4, 4));
+
+        let tiled = Image::from_raw(&mut tiled[0], width, height);
+        for &(x, y) in &[(0, 0), (2, 0), (0, 2), (2, 2)] {
+            assert_eq!(tiled.crop(x, y, 2, 2).0, pixels);
+        }
+    }
+
+    #[test]
+    fn test_variable_blur() {
+        let mut pixels = Vec::new();
+        let mut map = Vec::new();
+        for row in 0..4 {
+            for col in 0..8 {
+                pixels.push(if (row + col) % 2 == 0 { Pixel::rgb(255, 255, 255) } else { Pixel::rgb(0, 0, 0) });
+                map.push(if col < 4 { Pixel::rgb(0, 0, 0) } else { Pixel::rgb(255, 255, 255) });
+            }
+        }
+        let original = pixels.clone();
+
+        let blur_map = Image::from_raw(&mut map[0], 8, 4);
+        let mut image = Image::from_raw(&mut pixels[0], 8, 4);
+        image.variable_blur(&blur_map, 2.0);
+
+        for (i, original) in original.iter().enumerate() {
+            let (_, col) = image.index_to_row_col(i);
+            if col < 4 {
+                assert_eq!(image.pixels[i], *original);
+            } else {
+                let red = image.pixels[i].red;
+                assert!(red > 64 && red < 192);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sample_bilinear() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 1);
+
+        assert_eq!(image.sample_bilinear(0.5, 0.0), Pixel::rgb(128, 128, 128));
+        assert_eq!(image.sample_bilinear(0.0, 0.0), Pixel::rgb(0, 0, 0));
+        assert_eq!(image.sample_bilinear(1.0, 0.0), Pixel::rgb(255, 255, 255));
+        assert_eq!(image.sample_bilinear(-3.0, 2.5), Pixel::rgb(0, 0, 0));
+        assert_eq!(image.sample_bilinear(7.0, -1.0), Pixel::rgb(255, 255, 255));
+
+        let empty = Image { width: 0, height: 0, pixels: &mut [] };
+        assert_eq!(empty.sample_bilinear(0.5, 0.5), Pixel::rgba(0, 0, 0, 0));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut pixels = [
+            Pixel::rgb(0, 0, 0),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(255, 255, 255),
+            Pixel::rgb(50, 100, 150),
+        ];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+        let histogram = image.histogram();
+
+        assert_eq!(histogram[0], 1);
+        assert_eq!(histogram[91], 1);
+        assert_eq!(histogram[255], 2);
+        assert_eq!(histogram.iter().sum::<usize>(), 4);
+    }
+
+    #[test]
+    fn test_render_histogram() {
+        let mut pixels = [Pixel::rgb(128, 128, 128); 4];
+
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+        let rendered = image.render_histogram(256, 10);
+
+        assert_eq!(rendered.len(), 2560);
+        for (i, pixel) in rendered.iter().enumerate() {
+            if i % 256 == 128 {
+                assert_eq!(*pixel, Pixel::rgb(255, 255, 255));
+            } else {
+                assert_eq!(*pixel, Pixel::rgb(0, 0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sobel_full() {
+        let mut pixels = Vec::new();
+        for row in 0..4 {
+            for col in 0..4 {
+                pixels.push(Pixel::rgb((row * 60) as u8, (col * 40) as u8, 0));
+            }
+        }
+
+        let image = Image::from_raw(&mut pixels[0], 4, 4);
+        let full = image.sobel_full();
+        let magnitude = image.gradient_magnitude();
+        let angles = image.sobel_angles();
+
+        assert_eq!(full.len(), 16);
+        for (i, &(m, a)) in full.iter().enumerate() {
+            assert_eq!(m, magnitude[i]);
+            assert_eq!(a, angles[i]);
+        }
+    }
+
+    #[test]
+    fn test_harris_corners() {
+        let mut pixels = Vec::new();
+        for row in 0..20 {
+            for col in 0..20 {
+                let inside = (5..15).contains(&row) && (5..15).contains(&col);
+                pixels.push(if inside { Pixel::rgb(255, 255, 255) } else { Pixel::rgb(0, 0, 0) });
+            }
+        }
+
+        let image = Image::from_raw(&mut pixels[0], 20, 20);
+        let corners = image.harris_corners(0.04, 1e9);
+
+        assert_eq!(corners.len(), 4);
+        for &(x, y) in &[(5, 5), (14, 5), (5, 14), (14, 14)] {
+            assert!(corners.iter().any(|&(cx, cy)| {
+                (cx as isize - x).abs() <= 1 && (cy as isize - y).abs() <= 1
+            }));
+        }
+    }
+
+    #[test]
+    fn test_approx_eq() {
+        let mut a = [Pixel::rgb(100, 150, 200), Pixel::rgb(0, 0, 0)];
+        let mut b = [Pixel::rgb(101, 149, 201), Pixel::rgb(1, 1, 1)];
+
+        let first = Image::from_raw(&mut a[0], 2, 1);
+        let second = Image::from_raw(&mut b[0], 2, 1);
+
+        assert!(first.approx_eq(&second, 1));
+        assert!(!first.approx_eq(&second, 0));
+        assert!(first.approx_eq(&first, 0));
+
+        let mut c = a;
+        let reshaped = Image::from_raw(&mut c[0], 1, 2);
+        assert!(!first.approx_eq(&reshaped, 255));
+    }
+
+    #[test]
+    fn test_convolve_fn_large_integer_weights() {
+        let mut pixels = [Pixel::rgb(100, 200, 50); 25];
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.convolve_fn(2, |_, _| 1e9);
+        assert_eq!(image.pixels, [Pixel::rgb(100, 200, 50); 25]);
+
+        // The centre's weighted sum is 255 * 24e9, far past i32::MAX, and the
+        // sum-zero kernel leaves it unnormalized, so it has to clamp to 255
+        let mut pixels = [Pixel::rgb(0, 0, 0); 25];
+        pixels[12] = Pixel::rgb(255, 255, 255);
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.convolve_fn(2, |dx, dy| if dx == 0 && dy == 0 { 24e9 } else { -1e9 });
+
+        assert_eq!(image.pixels[12], Pixel::rgb(255, 255, 255));
+        assert_eq!(image.pixels[0], Pixel::rgb(0, 0, 0));
+        assert_eq!(image.pixels[7], Pixel::rgb(0, 0, 0));
+    }
+
+    #[test]
+    fn test_convolve_fn() {
+        let mut pixels = Vec::new();
+        for i in 0..25 {
+            pixels.push(Pixel::rgb((i * 10) as u8, (i * 3) as u8, 200 - (i * 7) as u8));
+        }
+        let mut expected = pixels.clone();
+
+        let mut image = Image::from_raw(&mut pixels[0], 5, 5);
+        image.convolve_fn(1, |_, _| 1.0);
+
+        let mut matrix_image = Image::from_raw(&mut expected[0], 5, 5);
+        matrix_image.filter(FilterType::Convolution([[1.0 / 9.0; 3]; 3]));
+
+        for row in 1..4 {
+            for col in 1..4 {
+                let i = image.row_col_to_index(row, col);
+                let (a, b) = (image.pixels[i], matrix_image.pixels[i]);
+                assert!((a.red as i16 - b.red as i16).abs() <= 1);
+                assert!((a.green as i16 - b.green as i16).abs() <= 1);
+                assert!((a.blue as i16 - b.blue as i16).abs() <= 1);
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "match the image dimensions")]
+    fn test_pyramid_blend_size_mismatch() {
+        let mut red = [Pixel::rgb(255, 0, 0); 8 * 8];
+        let mut blue = [Pixel::rgb(0, 0, 255); 4 * 4];
+        let mut mask = vec![Pixel::rgb(255, 255, 255); 8 * 8];
+
+        let a = Image::from_raw(&mut red[0], 8, 8);
+        let b = Image::from_raw(&mut blue[0], 4, 4);
+        let m = Image::from_raw(&mut mask[0], 8, 8);
+        a.pyramid_blend(&b, &m, 3);
+    }
+
+    #[test]
+    fn test_pyramid_blend() {
+        let mut red = vec![Pixel::rgb(255, 0, 0); 32 * 4];
+        let mut blue = vec![Pixel::rgb(0, 0, 255); 32 * 4];
+        let mut mask: Vec<Pixel> = (0..32 * 4)
+            .map(|i| {
+                let v = 255 - ((i % 32) * 255 / 31) as u8;
+                Pixel::rgb(v, v, v)
+            })
+            .collect();
+
+        let a = Image::from_raw(&mut red[0], 32, 4);
+        let b = Image::from_raw(&mut blue[0], 32, 4);
+        let m = Image::from_raw(&mut mask[0], 32, 4);
+
+        let blended = a.pyramid_blend(&b, &m, 4);
+
+        assert_eq!(blended.len(), 32 * 4);
+        assert!(blended[0].red > 200 && blended[0].blue < 55);
+        assert!(blended[31].blue > 200 && blended[31].red < 55);
+
+        for col in 1..32 {
+            let (prev, next) = (blended[col - 1], blended[col]);
+            assert!(next.red <= prev.red && next.blue >= prev.blue);
+            assert!(prev.red - next.red <= 20);
+        }
+    }
+
+    #[test]
+    fn test_map_region() {
+        let mut pixels = [Pixel::rgb(10, 10, 10); 16];
+
+        let mut image = Image::from_raw(&mut pixels[0], 4, 4);
+        image.map_region((1, 1, 2, 2), |p| p.set_gray(200));
+
+        for i in 0..16 {
+            let (row, col) = image.index_to_row_col(i);
+            let inside = (1..3).contains(&row) && (1..3).contains(&col);
+            let expected = if inside { 200 } else { 10 };
+            assert_eq!(image.pixels[i], Pixel::rgb(expected, expected, expected));
+        }
+
+        image.map_region((3, 3, 5, 5), |p| p.set_gray(0));
+        assert_eq!(image.pixels[15], Pixel::rgb(0, 0, 0));
+        assert_eq!(image.pixels[14], Pixel::rgb(10, 10, 10));
+    }
+
+    #[test]
+    fn test_from_fn() {
+        let mut owned = OwnedImage::from_fn(5, 2, |x, _| {
+            let v = (x * 60) as u8;
+            Pixel::rgb(v, v, v)
+        });
+
+        assert_eq!((owned.width, owned.height, owned.pixels.len()), (5, 2, 10));
+        assert_eq!(owned.pixels[0], Pixel::rgb(0, 0, 0));
+        assert_eq!(owned.pixels[2], Pixel::rgb(120, 120, 120));
+        assert_eq!(owned.pixels[9], Pixel::rgb(240, 240, 240));
+
+        let image = owned.as_image();
+        assert_eq!(image.pixels[image.row_col_to_index(1, 3)], Pixel::rgb(180, 180, 180));
+    }
+
+    #[test]
+    fn test_grayscale_with() {
+        let mut pixels = [Pixel::rgb(50, 100, 150), Pixel::rgb(255, 0, 0)];
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        let mode = image.grayscale_with(GrayMode::Rec709);
+
+        assert_eq!(mode, GrayMode::Rec709);
+        assert_eq!(image.pixels, [Pixel::rgb(93, 93, 93), Pixel::rgb(54, 54, 54)]);
+
+        let mut pixels = [Pixel::rgb(50, 100, 150)];
+        let mut image = Image::from_raw(&mut pixels[0], 1, 1);
+        assert_eq!(image.grayscale_with(GrayMode::Average), GrayMode::Average);
+        assert_eq!(image.pixels[0], Pixel::rgb(100, 100, 100));
+    }
+
+    #[test]
+    fn test_average_hash() {
+        let mut pixels: Vec<Pixel> = (0..256)
+            .map(|i| if i % 16 < 8 { Pixel::rgb(0, 0, 0) } else { Pixel::rgb(255, 255, 255) })
+            .collect();
+        let mut brighter: Vec<Pixel> = pixels.iter().map(|p| Pixel::rgb(p.red.max(20), p.green.max(20), p.blue.max(20))).collect();
+
+        let image = Image::from_raw(&mut pixels[0], 16, 16);
+        let similar = Image::from_raw(&mut brighter[0], 16, 16);
+
+        assert_eq!(image.average_hash(), 0xf0f0_f0f0_f0f0_f0f0);
+        assert_eq!(image.average_hash(), similar.average_hash());
+    }
+
+    #[test]
+    fn test_overlay_scalar() {
+        let mut pixels = [Pixel::rgb(128, 128, 128); 3];
+        let values = [0.0, 5.0, 10.0];
+
+        let mut image = Image::from_raw(&mut pixels[0], 3, 1);
+        image.overlay_scalar(&values, 0.5);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(64, 64, 192),
+            Pixel::rgb(128, 64, 128),
+            Pixel::rgb(192, 64, 64),
+        ]);
+    }
+
+    #[test]
+    fn test_rgba_bytes() {
+        let straight = [200, 100, 50, 128, 10, 20, 30, 255];
+
+        let mut owned = OwnedImage::from_rgba_bytes(&straight, 2, 1, AlphaMode::Straight);
+        assert_eq!(owned.pixels, vec![Pixel::rgba(200, 100, 50, 128), Pixel::rgb(10, 20, 30)]);
+        assert_eq!(owned.as_image().to_rgba_bytes(AlphaMode::Straight), straight.to_vec());
+
+        let premultiplied = owned.as_image().to_rgba_bytes(AlphaMode::Premultiplied);
+        assert_eq!(premultiplied, vec![100, 50, 25, 128, 10, 20, 30, 255]);
+
+        let mut owned = OwnedImage::from_rgba_bytes(&premultiplied, 2, 1, AlphaMode::Premultiplied);
+        assert_eq!(owned.pixels, vec![Pixel::rgba(199, 100, 50, 128), Pixel::rgb(10, 20, 30)]);
+        assert_eq!(owned.as_image().to_rgba_bytes(AlphaMode::Premultiplied), premultiplied);
+    }
+
+    #[test]
+    fn test_embed_message() {
+        let mut pixels: Vec<Pixel> = (0..64).map(|i| Pixel::rgb(i as u8 * 4, 128, 255 - i as u8)).collect();
+        let original = pixels.clone();
+        let mut image = Image::from_raw(&mut pixels[0], 8, 8);
+
+        assert!(image.embed_message(b"hello booth").is_ok());
+        assert_eq!(image.extract_message(), b"hello booth".to_vec());
+        assert!(image.approx_eq(&Image::from_raw(&mut original.clone()[0], 8, 8), 1));
+
+        assert_eq!(image.embed_message(&[0; 21]), Err(MessageError::TooLarge { capacity: 24, needed: 25 }));
+    }
+
+    #[test]
+    fn test_orientation_histogram() {
+        let mut owned = OwnedImage::from_fn(12, 12, |x, _| if x % 4 < 2 { Pixel::rgb(0, 0, 0) } else { Pixel::rgb(255, 255, 255) });
+        let histogram = owned.as_image().orientation_histogram(6);
+
+        let peak = (0..6).max_by(|&a, &b| histogram[a].partial_cmp(&histogram[b]).unwrap()).unwrap();
+        assert_eq!(peak, 0);
+        assert!(histogram[1..].iter().all(|&h| h < histogram[0] / 10.0));
+    }
+
+    #[test]
+    fn test_hue_cycle_frames() {
+        let mut pixels = [Pixel::rgb(255, 0, 0), Pixel::rgb(40, 200, 120)];
+        let image = Image::from_raw(&mut pixels[0], 2, 1);
+
+        assert_eq!(image.hue_cycle_frames(1), vec![image.pixels.to_vec()]);
+
+        let frames = image.hue_cycle_frames(2);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0], image.pixels.to_vec());
+        assert_eq!(frames[1][0], Pixel::rgb(0, 255, 255));
+
+        let mut rotated = image.pixels.to_vec();
+        let mut rotated_image = Image::from_raw(&mut rotated[0], 2, 1);
+        rotated_image.filter(FilterType::HueRotate(180.0));
+        assert_eq!(frames[1], rotated);
+    }
+
+    #[test]
+    fn test_polaroid() {
+        let mut pixels: Vec<Pixel> = (0..6).map(|i| Pixel::rgb(i * 40, 0, 0)).collect();
+        let image = Image::from_raw(&mut pixels[0], 3, 2);
+        let frame = Pixel::rgb(250, 250, 240);
+
+        let (framed, width, height) = image.polaroid(2, 3, frame);
+        assert_eq!((width, height), (7, 9));
+
+        for row in 0..height {
+            for col in 0..width {
+                let inside = (2..4).contains(&row) && (2..5).contains(&col);
+                let expected = if inside { image.pixels[(row - 2) * 3 + col - 2] } else { frame };
+                assert_eq!(framed[row * width + col], expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_add_flare() {
+        let mut pixels = [Pixel::rgb(20, 20, 20); 81];
+        let mut image = Image::from_raw(&mut pixels[0], 9, 9);
+        image.add_flare(4, 4, 3.0, Pixel::rgb(200, 150, 100));
+
+        assert_eq!(image.pixels[image.row_col_to_index(4, 4)], Pixel::rgb(220, 170, 120));
+        assert!(image.pixels[image.row_col_to_index(4, 5)].red > 20);
+        assert_eq!(image.pixels[image.row_col_to_index(4, 7)], Pixel::rgb(20, 20, 20));
+        assert_eq!(image.pixels[0], Pixel::rgb(20, 20, 20));
+    }
+
+    #[test]
+    fn test_local_variance() {
+        let mut flat = OwnedImage::from_fn(6, 6, |_, _| Pixel::rgb(90, 90, 90));
+        assert!(flat.as_image().local_variance(1).iter().all(|&v| v == 0));
+
+        let mut checker = OwnedImage::from_fn(6, 6, |x, y| {
+            if (x + y) % 2 == 0 {
+                Pixel::rgb(0, 0, 0)
+            } else {
+                Pixel::rgb(255, 255, 255)
+            }
+        });
+        assert!(checker.as_image().local_variance(1).iter().all(|&v| v > 200));
+    }
+
+    #[test]
+    fn test_distance_transform() {
+        let mut owned = OwnedImage::from_fn(7, 7, |x, y| if (x, y) == (3, 3) { Pixel::rgb(255, 255, 255) } else { Pixel::rgb(0, 0, 0) });
+        let distances = owned.as_image().distance_transform();
+        let at = |x: usize, y: usize| distances[y * 7 + x];
+
+        assert_eq!(at(3, 3), 0.0);
+        assert_eq!(at(4, 3), 1.0);
+        assert_eq!(at(5, 3), 2.0);
+        assert_eq!(at(3, 6), 3.0);
+        assert!((at(4, 4) - 2f64.sqrt()).abs() < 1e-9);
+        assert!(at(0, 0) > at(1, 1) && at(1, 1) > at(2, 2) && at(2, 2) > at(3, 3));
+    }
+
+    #[test]
+    fn test_drop_shadow() {
+        let mut owned = OwnedImage::from_fn(4, 4, |_, _| Pixel::rgb(255, 0, 0));
+        let (pixels, width, height) = owned.as_image().drop_shadow((3, 2), 1.0, Pixel::rgb(0, 0, 0));
+        assert_eq!((width, height), (4 + 3 + 3 + 3, 4 + 3 + 3 + 2));
+
+        let at = |x: usize, y: usize| pixels[y * width + x];
+
+        // original at (3, 3), fully covering its shadow where they overlap
+        assert_eq!(at(3, 3), Pixel::rgb(255, 0, 0));
+        assert_eq!(at(6, 6), Pixel::rgb(255, 0, 0));
+
+        // shadow core below and to the right, faded at its rim, absent far away
+        let core = at(8, 7);
+        assert_eq!((core.red, core.green, core.blue), (0, 0, 0));
+        assert!(core.alpha > 200);
+        assert!(at(10, 7).alpha > 0 && at(10, 7).alpha < core.alpha);
+        assert_eq!(at(0, height - 1).alpha, 0);
+        assert_eq!(at(width - 1, 0).alpha, 0);
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut pixels: Vec<Pixel> = (0..9).map(|i| Pixel::rgb(i, 0, 0)).collect();
+        let image = Image::from_raw(&mut pixels[0], 3, 3);
+        let reds = |window: &[Pixel]| window.iter().map(|p| p.red).collect::<Vec<u8>>();
+
+        let windows: Vec<(usize, Vec<Pixel>)> = image.windows(1, EdgeMode::Clamp).collect();
+        assert_eq!(windows.len(), 9);
+        assert_eq!(windows[4].0, 4);
+        assert_eq!(reds(&windows[4].1), vec![0, 1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(reds(&windows[0].1), vec![0, 0, 1, 0, 0, 1, 3, 3, 4]);
+
+        let (_, wrapped) = image.windows(1, EdgeMode::Wrap).next().unwrap();
+        assert_eq!(reds(&wrapped), vec![8, 6, 7, 2, 0, 1, 5, 3, 4]);
+
+        let (_, mirrored) = image.windows(1, EdgeMode::Mirror).next().unwrap();
+        assert_eq!(reds(&mirrored), vec![4, 3, 4, 1, 0, 1, 4, 3, 4]);
+    }
+
+    #[test]
+    fn test_to_ppm() {
+        let mut pixels = [Pixel::rgb(1, 2, 3), Pixel::rgb(4, 5, 6), Pixel::rgb(7, 8, 9), Pixel::rgb(250, 128, 0)];
+        let image = Image::from_raw(&mut pixels[0], 2, 2);
+        let ppm = image.to_ppm();
+
+        let header = b"P6\n2 2\n255\n";
+        assert_eq!(&ppm[..header.len()], &header[..]);
+
+        let body = &ppm[header.len()..];
+        assert_eq!(body.len(), 12);
+        assert_eq!(&body[9..12], &[250, 128, 0]);
+    }
+
+    #[test]
+    fn test_from_ppm() {
+        let mut owned = OwnedImage::from_fn(5, 3, |x, y| Pixel::rgb(x as u8 * 50, y as u8 * 100, 7));
+        let ppm = owned.as_image().to_ppm();
+
+        let loaded = OwnedImage::from_ppm(&ppm).unwrap();
+        assert_eq!((loaded.width, loaded.height), (5, 3));
+        assert_eq!(loaded.pixels, owned.pixels);
+
+        let gray = b"P5 # two by one\n2 1\n15\n\x00\x0f";
+        let loaded = OwnedImage::from_ppm(gray).unwrap();
+        assert_eq!(loaded.pixels, vec![Pixel::rgb(0, 0, 0), Pixel::rgb(255, 255, 255)]);
+
+        assert_eq!(OwnedImage::from_ppm(b"P3\n1 1\n255\n").err(), Some(ParseError::UnsupportedFormat));
+        assert_eq!(OwnedImage::from_ppm(b"P6\n2 2\n255\n\x00").err(), Some(ParseError::Truncated));
+        assert_eq!(OwnedImage::from_ppm(b"P6 4294967296 4294967296 255\n\x00").err(), Some(ParseError::InvalidHeader));
+    }
+
+    #[test]
+    fn test_detect_skew() {
+        let slope = 5f64.to_radians().tan();
+        let mut owned = OwnedImage::from_fn(120, 90, |x, y| {
+            let offset = (y as f64 - x as f64 * slope).rem_euclid(12.0);
+            if offset < 2.0 {
+                Pixel::rgb(0, 0, 0)
+            } else {
+                Pixel::rgb(255, 255, 255)
+            }
+        });
+
+        let skew = owned.as_image().detect_skew();
+        assert!((skew - 5.0).abs() < 1.0, "detected {}", skew);
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut pixels: Vec<Pixel> = (0..6).map(|i| Pixel::rgb(i * 40, 0, 0)).collect();
+        let image = Image::from_raw(&mut pixels[0], 3, 2);
+
+        let (rotated, width, height) = image.rotate(90.0, Pixel::rgb(0, 0, 255));
+        assert_eq!((width, height), (2, 3));
+        let reds: Vec<u8> = rotated.iter().map(|p| p.red).collect();
+        assert_eq!(reds, vec![120, 0, 160, 40, 200, 80]);
+
+        let (same, width, height) = image.rotate(0.0, Pixel::rgb(0, 0, 255));
+        assert_eq!((width, height), (3, 2));
+        assert_eq!(same, image.pixels.to_vec());
+    }
+
+    #[test]
+    fn test_auto_deskew() {
+        let slope = 5f64.to_radians().tan();
+        let mut owned = OwnedImage::from_fn(120, 90, |x, y| {
+            let offset = (y as f64 - x as f64 * slope).rem_euclid(12.0);
+            if offset < 2.0 && x > 10 && x < 110 {
+                Pixel::rgb(0, 0, 0)
+            } else {
+                Pixel::rgb(255, 255, 255)
+            }
+        });
+
+        let (pixels, width, height) = owned.as_image().auto_deskew(Pixel::rgb(255, 255, 255));
+        let mut straightened = OwnedImage::new(pixels, width, height);
+        assert!(straightened.as_image().detect_skew().abs() < 1.0);
+    }
+
+    #[test]
+    fn test_split_merge_channels() {
+        let mut owned = OwnedImage::from_fn(4, 3, |x, y| Pixel::rgb(x as u8 * 60, y as u8 * 90, (x * y) as u8));
+        let (red, green, blue) = owned.as_image().split_channels();
+        assert_eq!(red.len(), 12);
+        assert_eq!((red[5], green[5], blue[5]), (60, 90, 1));
+
+        let merged = OwnedImage::merge_channels(&red, &green, &blue, 4, 3);
+        assert_eq!((merged.width, merged.height), (4, 3));
+        assert_eq!(merged.pixels, owned.pixels);
+    }
+
+    #[test]
+    fn test_load_rgba_limited() {
+        let bytes = [10, 20, 30, 255, 40, 50, 60, 128];
+
+        let loaded = OwnedImage::load_rgba_limited(&bytes, 2, 1, 4).unwrap();
+        assert_eq!(loaded.pixels, vec![Pixel::rgba(10, 20, 30, 255), Pixel::rgba(40, 50, 60, 128)]);
+
+        assert_eq!(
+            OwnedImage::load_rgba_limited(&bytes, 100_000, 100, 4_000_000).err(),
+            Some(LimitError::TooManyPixels { declared: Some(10_000_000), max: 4_000_000 })
+        );
+        assert_eq!(
+            OwnedImage::load_rgba_limited(&bytes, usize::MAX, 2, 4_000_000).err(),
+            Some(LimitError::TooManyPixels { declared: None, max: 4_000_000 })
+        );
+        assert_eq!(
+            OwnedImage::load_rgba_limited(&bytes, usize::MAX / 2, 1, usize::MAX).err(),
+            Some(LimitError::TooManyPixels { declared: Some(usize::MAX / 2), max: usize::MAX })
+        );
+        assert_eq!(
+            OwnedImage::load_rgba_limited(&bytes, 2, 2, 4).err(),
+            Some(LimitError::LengthMismatch { expected: 16, actual: 8 })
+        );
+    }
+
+    #[test]
+    fn test_skin_mask() {
+        let mut pixels = [Pixel::rgb(224, 172, 140), Pixel::rgb(0, 0, 255), Pixel::rgb(128, 128, 128)];
+        let image = Image::from_raw(&mut pixels[0], 3, 1);
+
+        assert_eq!(image.skin_mask(), vec![Pixel::rgb(255, 255, 255), Pixel::rgb(0, 0, 0), Pixel::rgb(0, 0, 0)]);
+    }
+
+    #[test]
+    fn test_alpha_as_gray() {
+        let mut pixels = [Pixel::rgba(10, 200, 30, 128), Pixel::rgba(0, 0, 0, 0), Pixel::rgb(5, 5, 5)];
+        let image = Image::from_raw(&mut pixels[0], 3, 1);
+
+        assert_eq!(image.alpha_as_gray(), vec![Pixel::rgb(128, 128, 128), Pixel::rgb(0, 0, 0), Pixel::rgb(255, 255, 255)]);
+    }
+
+    #[test]
+    fn test_over_checkerboard() {
+        let (light, dark) = (Pixel::rgb(204, 204, 204), Pixel::rgb(153, 153, 153));
+        let clear = Pixel::rgba(0, 0, 0, 0);
+        let mut pixels = [Pixel::rgb(255, 0, 0), clear, clear, clear, clear, clear, clear, clear];
+        let image = Image::from_raw(&mut pixels[0], 4, 2);
+
+        let preview = image.over_checkerboard(2, light, dark);
+        assert_eq!(preview, vec![Pixel::rgb(255, 0, 0), light, dark, dark, light, light, dark, dark]);
+    }
+
+    #[test]
+    fn test_entropy() {
+        let mut solid = OwnedImage::from_fn(8, 8, |_, _| Pixel::rgb(30, 60, 90));
+        assert_eq!(solid.as_image().entropy(), 0.0);
+
+        let mut ramp = OwnedImage::from_fn(16, 16, |x, y| {
+            let v = ((y * 16 + x) * 97 % 256) as u8;
+            Pixel::rgb(v, v, v)
+        });
+        assert!((ramp.as_image().entropy() - 8.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_convolve_reporting() {
+        let bright: ConvolutionMatrix = [[0.5, 0.5, 0.5], [0.5, 1.0, 0.5], [0.5, 0.5, 0.5]];
+        let mut owned = OwnedImage::from_fn(6, 6, |x, _| Pixel::rgb(if x < 3 { 20 } else { 120 }, 20, 20));
+
+        let report = owned.as_image().convolve_reporting(bright);
+        assert_eq!(report, ConvolutionReport { clamped_low: 0, clamped_high: 8 });
+        assert_eq!(owned.pixels[6 * 4 + 4], Pixel::rgb(255, 100, 100));
+
+        let mut owned = OwnedImage::from_fn(6, 6, |_, _| Pixel::rgb(20, 20, 20));
+        let report = owned.as_image().convolve_reporting(BLUR);
+        assert_eq!(report, ConvolutionReport { clamped_low: 0, clamped_high: 0 });
+    }
+
+    #[test]
+    fn test_process_tiles() {
+        let make = || OwnedImage::from_fn(11, 7, |x, y| Pixel::rgb((x * 20) as u8, (y * 30) as u8, 77));
+
+        let mut whole = make();
+        whole.as_image().filter(FilterType::Invert);
+
+        let mut tiled = make();
+        let mut origins = Vec::new();
+        tiled.as_image().process_tiles(4, 2, |tile, x, y| {
+            tile.filter(FilterType::Invert);
+            origins.push((x, y));
+        });
+
+        assert_eq!(tiled.pixels, whole.pixels);
+        assert_eq!(origins.len(), 6);
+        assert_eq!(origins[5], (8, 4));
+
+        let mut blurred_whole = make();
+        blurred_whole.as_image().filter(FilterType::GaussianBlur(0.6));
+        let mut blurred_tiles = make();
+        blurred_tiles.as_image().process_tiles(4, 3, |tile, _, _| tile.filter(FilterType::GaussianBlur(0.6)));
+        assert_eq!(blurred_tiles.pixels, blurred_whole.pixels);
     }
 }
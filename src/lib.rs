@@ -1,8 +1,19 @@
+// The modules stay private to the wasm build, so items beyond what
+// apply_filters reaches are marked cfg_attr(not(test), allow(dead_code))
+// where they are defined. The test build still flags anything unused there
+
+#[cfg_attr(not(test), allow(dead_code))]
+mod accumulator;
 mod bitflags;
+#[cfg_attr(not(test), allow(dead_code))]
+mod cache;
 mod filter;
 mod image;
 mod pixel;
 mod convolution;
+#[cfg_attr(not(test), allow(dead_code))]
+mod pipeline;
+mod random;
 
 use bitflags::BitFlags;
 use filter::FilterType;
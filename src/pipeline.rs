@@ -0,0 +1,77 @@
+use filter::{FilterType, ImageFilterExt};
+use image::Image;
+use pixel::Pixel;
+
+pub struct FilterPipeline {
+    pub filters: Vec<FilterType>,
+}
+
+impl FilterPipeline {
+    pub fn new(filters: Vec<FilterType>) -> FilterPipeline {
+        FilterPipeline { filters }
+    }
+
+    pub fn apply(&self, image: &mut Image) {
+        for filter in self.filters.iter() {
+            image.filter(filter.clone());
+        }
+    }
+
+    // Same as apply but also returns a copy of the pixels after each stage,
+    // useful for working out which filter in a chain went wrong
+
+    pub fn apply_capturing(&self, image: &mut Image) -> Vec<Vec<Pixel>> {
+        let mut snapshots = Vec::with_capacity(self.filters.len());
+
+        for filter in self.filters.iter() {
+            image.filter(filter.clone());
+            snapshots.push(image.pixels.to_vec());
+        }
+
+        snapshots
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_apply() {
+        let mut pixels = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let pipeline = FilterPipeline::new(vec![FilterType::Invert, FilterType::Grayscale]);
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        pipeline.apply(&mut image);
+
+        assert_eq!(image.pixels, [
+            Pixel::rgb(105, 105, 105),
+            Pixel::rgb(255, 255, 255),
+        ]);
+    }
+
+    #[test]
+    fn test_apply_capturing() {
+        let mut pixels = [
+            Pixel::rgb(100, 150, 200),
+            Pixel::rgb(0, 0, 0),
+        ];
+
+        let pipeline = FilterPipeline::new(vec![FilterType::Invert, FilterType::Grayscale]);
+
+        let mut image = Image::from_raw(&mut pixels[0], 2, 1);
+        let snapshots = pipeline.apply_capturing(&mut image);
+
+        assert_eq!(snapshots.len(), 2);
+        assert_eq!(snapshots[0], vec![
+            Pixel::rgb(155, 105, 55),
+            Pixel::rgb(255, 255, 255),
+        ]);
+        assert_eq!(snapshots[1], image.pixels.to_vec());
+    }
+}
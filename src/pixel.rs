@@ -1,9 +1,138 @@
+// Round a float channel value to the nearest u8, saturating at 0 and 255
+// rather than wrapping
+
+pub fn clamp_channel(v: f64) -> u8 {
+    v.round().clamp(0.0, 255.0) as u8
+}
+
+// sRGB transfer function, between 0..255 encoded values and 0..1 linear light
+
+pub fn srgb_to_linear(v: u8) -> f64 {
+    let v = v as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(v: f64) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let encoded = if v <= 0.0031308 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    clamp_channel(encoded * 255.0)
+}
+
+// CIE L* (0..100) from linear relative luminance, and back
+
+pub fn linear_to_lightness(y: f64) -> f64 {
+    if y > 216.0 / 24389.0 {
+        116.0 * y.cbrt() - 16.0
+    } else {
+        y * 24389.0 / 27.0
+    }
+}
+
+pub fn lightness_to_linear(l: f64) -> f64 {
+    if l > 8.0 {
+        ((l + 16.0) / 116.0).powi(3)
+    } else {
+        l * 27.0 / 24389.0
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum GrayMode {
+    Average,
+    Rec601,
+    Rec709,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl GrayMode {
+    pub fn weights(&self) -> (f64, f64, f64) {
+        match *self {
+            GrayMode::Average => (1.0, 1.0, 1.0),
+            GrayMode::Rec601 => (0.299, 0.587, 0.114),
+            GrayMode::Rec709 => (0.2126, 0.7152, 0.0722),
+        }
+    }
+
+    pub fn describe(&self) -> &'static str {
+        match *self {
+            GrayMode::Average => "Average of red, green and blue",
+            GrayMode::Rec601 => "Rec.601 luma (0.299, 0.587, 0.114)",
+            GrayMode::Rec709 => "Rec.709 luma (0.2126, 0.7152, 0.0722)",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum BlendMode {
+    Normal,
+    Multiply,
+    Screen,
+    Overlay,
+    Add,
+}
+
+impl BlendMode {
+    // Blend a top channel over a base channel, both in 0..1
+
+    pub fn apply(&self, base: f64, top: f64) -> f64 {
+        match *self {
+            BlendMode::Normal => top,
+            BlendMode::Multiply => base * top,
+            BlendMode::Screen => 1.0 - (1.0 - base) * (1.0 - top),
+            BlendMode::Overlay if base < 0.5 => 2.0 * base * top,
+            BlendMode::Overlay => 1.0 - 2.0 * (1.0 - base) * (1.0 - top),
+            BlendMode::Add => (base + top).min(1.0),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(not(test), allow(dead_code))]
+pub enum ColorBlindType {
+    Protanopia,
+    Deuteranopia,
+    Tritanopia,
+}
+
+// Linear sRGB to LMS cone space and back, with the dichromat projections of
+// Vienot, Brettel and Mollon (1999) replacing the missing cone's response
+
+static RGB_TO_LMS: [[f64; 3]; 3] = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+
+static LMS_TO_RGB: [[f64; 3]; 3] = [
+    [0.0809444479, -0.130504409, 0.116721066],
+    [-0.0102485335, 0.0540193266, -0.113614708],
+    [-0.000365296938, -0.00412161469, 0.693511405],
+];
+
+fn multiply(m: &[[f64; 3]; 3], v: [f64; 3]) -> [f64; 3] {
+    [
+        m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+        m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+        m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+    ]
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Pixel {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
-    _alpha: u8,
+    pub alpha: u8,
 }
 
 impl Pixel {
@@ -12,7 +141,16 @@ impl Pixel {
             red,
             green,
             blue,
-            _alpha: 255,
+            alpha: 255,
+        }
+    }
+
+    pub fn rgba(red: u8, green: u8, blue: u8, alpha: u8) -> Pixel {
+        Pixel {
+            red,
+            green,
+            blue,
+            alpha,
         }
     }
 
@@ -37,6 +175,188 @@ impl Pixel {
         let avg = ((self.green as u32 + self.red as u32 + self.blue as u32) / 3) as u8;
         self.set_gray(avg);
     }
+
+    pub fn luminance(&self) -> u8 {
+        clamp_channel(self.luminance_f64())
+    }
+
+    // Rec.601 luma without rounding
+
+    pub fn luminance_f64(&self) -> f64 {
+        0.299 * self.red as f64 + 0.587 * self.green as f64 + 0.114 * self.blue as f64
+    }
+
+    // Weighted sum of the channels divided by the total weight, so any
+    // weighting stays within range (e.g. Rec.601 or Rec.709 coefficients)
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn weighted_gray(&self, wr: f64, wg: f64, wb: f64) -> u8 {
+        let total = wr + wg + wb;
+        if total == 0.0 {
+            return 0;
+        }
+
+        let sum = wr * self.red as f64 + wg * self.green as f64 + wb * self.blue as f64;
+        clamp_channel(sum / total)
+    }
+
+    // Linear light luminance using the sRGB / Rec.709 primaries
+
+    pub fn linear_luminance(&self) -> f64 {
+        0.2126 * srgb_to_linear(self.red) + 0.7152 * srgb_to_linear(self.green) + 0.0722 * srgb_to_linear(self.blue)
+    }
+
+    // Perceptual lightness, CIE L* from 0 to 100
+
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn lightness(&self) -> f64 {
+        linear_to_lightness(self.linear_luminance())
+    }
+
+    // How the colour appears to a viewer with the given dichromacy
+
+    pub fn simulate_color_blindness(&self, kind: ColorBlindType) -> Pixel {
+        let linear = [srgb_to_linear(self.red), srgb_to_linear(self.green), srgb_to_linear(self.blue)];
+        let [l, m, s] = multiply(&RGB_TO_LMS, linear);
+
+        let lms = match kind {
+            ColorBlindType::Protanopia => [2.02344 * m - 2.52581 * s, m, s],
+            ColorBlindType::Deuteranopia => [l, 0.494207 * l + 1.24827 * s, s],
+            ColorBlindType::Tritanopia => [l, m, -0.395913 * l + 0.801109 * m],
+        };
+
+        let rgb = multiply(&LMS_TO_RGB, lms);
+        Pixel::rgba(linear_to_srgb(rgb[0]), linear_to_srgb(rgb[1]), linear_to_srgb(rgb[2]), self.alpha)
+    }
+
+    // Blend top onto this pixel with the given mode, mixed in by top's alpha.
+    // The result keeps this pixel's alpha
+
+    pub fn blend(&self, top: &Pixel, mode: BlendMode) -> Pixel {
+        let opacity = top.alpha as f64 / 255.0;
+        let channel = |base: u8, over: u8| {
+            let (b, t) = (base as f64 / 255.0, over as f64 / 255.0);
+            clamp_channel((b + (mode.apply(b, t) - b) * opacity) * 255.0)
+        };
+
+        Pixel::rgba(
+            channel(self.red, top.red),
+            channel(self.green, top.green),
+            channel(self.blue, top.blue),
+            self.alpha,
+        )
+    }
+
+    // Hue in degrees 0..360, saturation and value 0..1
+
+    pub fn to_hsv(self) -> (f64, f64, f64) {
+        let (r, g, b) = (self.red as f64 / 255.0, self.green as f64 / 255.0, self.blue as f64 / 255.0);
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+        (hue, saturation, max)
+    }
+
+    pub fn from_hsv(hue: f64, saturation: f64, value: f64, alpha: u8) -> Pixel {
+        let hue = hue.rem_euclid(360.0);
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - ((hue / 60.0).rem_euclid(2.0) - 1.0).abs());
+        let m = value - chroma;
+
+        let (r, g, b) = match (hue / 60.0) as u8 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        Pixel::rgba(
+            clamp_channel((r + m) * 255.0),
+            clamp_channel((g + m) * 255.0),
+            clamp_channel((b + m) * 255.0),
+            alpha,
+        )
+    }
+
+    pub fn hue_rotated(&self, degrees: f64) -> Pixel {
+        let (hue, saturation, value) = self.to_hsv();
+        Pixel::from_hsv(hue + degrees, saturation, value, self.alpha)
+    }
+
+    // Full-range BT.601 YCbCr as used by JPEG
+
+    pub fn to_ycbcr(self) -> (f64, f64, f64) {
+        let (r, g, b) = (self.red as f64, self.green as f64, self.blue as f64);
+        let y = 0.299 * r + 0.587 * g + 0.114 * b;
+        let cb = 128.0 - 0.168736 * r - 0.331264 * g + 0.5 * b;
+        let cr = 128.0 + 0.5 * r - 0.418688 * g - 0.081312 * b;
+        (y, cb, cr)
+    }
+
+    pub fn from_ycbcr(y: f64, cb: f64, cr: f64) -> Pixel {
+        Pixel::rgb(
+            clamp_channel(y + 1.402 * (cr - 128.0)),
+            clamp_channel(y - 0.344136 * (cb - 128.0) - 0.714136 * (cr - 128.0)),
+            clamp_channel(y + 1.772 * (cb - 128.0)),
+        )
+    }
+
+    // Colour channels scaled by alpha, as premultiplied formats store them
+
+    pub fn premultiplied(&self) -> Pixel {
+        let scale = |v: u8| clamp_channel(v as f64 * self.alpha as f64 / 255.0);
+        Pixel::rgba(scale(self.red), scale(self.green), scale(self.blue), self.alpha)
+    }
+
+    // Inverse of premultiplied. Fully transparent pixels come back black
+
+    pub fn unpremultiplied(&self) -> Pixel {
+        if self.alpha == 0 {
+            return Pixel::rgba(0, 0, 0, 0);
+        }
+        let scale = |v: u8| clamp_channel(v as f64 * 255.0 / self.alpha as f64);
+        Pixel::rgba(scale(self.red), scale(self.green), scale(self.blue), self.alpha)
+    }
+
+    // Source-over compositing of this pixel onto a background, working in
+    // premultiplied space and converting back to straight alpha. An opaque
+    // background always produces an opaque result
+
+    pub fn over(&self, background: &Pixel) -> Pixel {
+        let src_a = self.alpha as f64 / 255.0;
+        let dst_a = background.alpha as f64 / 255.0;
+        let out_a = src_a + dst_a * (1.0 - src_a);
+
+        if out_a == 0.0 {
+            return Pixel::rgba(0, 0, 0, 0);
+        }
+
+        let blend = |src: u8, dst: u8| {
+            let premultiplied = src as f64 * src_a + dst as f64 * dst_a * (1.0 - src_a);
+            clamp_channel(premultiplied / out_a)
+        };
+
+        Pixel::rgba(
+            blend(self.red, background.red),
+            blend(self.green, background.green),
+            blend(self.blue, background.blue),
+            clamp_channel(out_a * 255.0),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -44,6 +364,22 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_clamp_channel() {
+        assert_eq!(clamp_channel(-1.0), 0);
+        assert_eq!(clamp_channel(0.0), 0);
+        assert_eq!(clamp_channel(127.5), 128);
+        assert_eq!(clamp_channel(255.0), 255);
+        assert_eq!(clamp_channel(300.0), 255);
+    }
+
+    #[test]
+    fn test_luminance_f64() {
+        assert_eq!(Pixel::rgb(0, 0, 0).luminance_f64(), 0.0);
+        assert!((Pixel::rgb(255, 255, 255).luminance_f64() - 255.0).abs() < 1e-9);
+        assert!((Pixel::rgb(50, 100, 150).luminance_f64() - 90.75).abs() < 1e-9);
+    }
+
     #[test]
     fn test_rgb() {
         let pixel = Pixel::rgb(50, 100, 150);
@@ -103,4 +439,117 @@ mod tests {
         assert_eq!(pixel.green, 100);
         assert_eq!(pixel.blue, 100);
     }
+
+    #[test]
+    fn test_luminance() {
+        assert_eq!(Pixel::rgb(0, 0, 0).luminance(), 0);
+        assert_eq!(Pixel::rgb(255, 255, 255).luminance(), 255);
+        assert_eq!(Pixel::rgb(50, 100, 150).luminance(), 91);
+    }
+
+    #[test]
+    fn test_weighted_gray() {
+        let pixel = Pixel::rgb(50, 100, 150);
+
+        assert_eq!(pixel.weighted_gray(1.0, 0.0, 0.0), 50);
+        assert_eq!(pixel.weighted_gray(0.0, 0.0, 2.0), 150);
+        assert_eq!(pixel.weighted_gray(1.0, 1.0, 1.0), 100);
+        assert_eq!(pixel.weighted_gray(0.299, 0.587, 0.114), pixel.luminance());
+        assert_eq!(pixel.weighted_gray(0.0, 0.0, 0.0), 0);
+    }
+
+    #[test]
+    fn test_gray_mode() {
+        assert_eq!(GrayMode::Rec601.weights(), (0.299, 0.587, 0.114));
+        assert_eq!(GrayMode::Average.describe(), "Average of red, green and blue");
+        assert_eq!(GrayMode::Rec709.describe(), "Rec.709 luma (0.2126, 0.7152, 0.0722)");
+    }
+
+    #[test]
+    fn test_srgb_linear() {
+        assert_eq!(srgb_to_linear(0), 0.0);
+        assert_eq!(srgb_to_linear(255), 1.0);
+        assert!((srgb_to_linear(128) - 0.2158).abs() < 1e-3);
+
+        for v in 0..256 {
+            assert_eq!(linear_to_srgb(srgb_to_linear(v as u8)), v as u8);
+        }
+    }
+
+    #[test]
+    fn test_lightness() {
+        assert_eq!(Pixel::rgb(0, 0, 0).lightness(), 0.0);
+        assert!((Pixel::rgb(255, 255, 255).lightness() - 100.0).abs() < 1e-9);
+        assert!((Pixel::rgb(119, 119, 119).lightness() - 50.0).abs() < 0.5);
+        assert!((lightness_to_linear(linear_to_lightness(0.3)) - 0.3).abs() < 1e-9);
+        assert!((lightness_to_linear(linear_to_lightness(0.001)) - 0.001).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_simulate_color_blindness() {
+        let gray = Pixel::rgb(128, 128, 128);
+        for &kind in &[ColorBlindType::Protanopia, ColorBlindType::Deuteranopia, ColorBlindType::Tritanopia] {
+            let simulated = gray.simulate_color_blindness(kind);
+            assert!((simulated.red as i16 - 128).abs() <= 1);
+            assert!((simulated.green as i16 - 128).abs() <= 1);
+            assert!((simulated.blue as i16 - 128).abs() <= 1);
+        }
+    }
+
+    #[test]
+    fn test_blend() {
+        let base = Pixel::rgb(200, 100, 0);
+        let top = Pixel::rgb(128, 255, 255);
+
+        assert_eq!(base.blend(&top, BlendMode::Normal), top);
+        assert_eq!(base.blend(&top, BlendMode::Multiply), Pixel::rgb(100, 100, 0));
+        assert_eq!(base.blend(&top, BlendMode::Screen), Pixel::rgb(228, 255, 255));
+        assert_eq!(base.blend(&top, BlendMode::Overlay), Pixel::rgb(200, 200, 0));
+        assert_eq!(base.blend(&top, BlendMode::Add), Pixel::rgb(255, 255, 255));
+        assert_eq!(base.blend(&Pixel::rgba(0, 0, 0, 0), BlendMode::Multiply), base);
+    }
+
+    #[test]
+    fn test_hue_rotated() {
+        assert_eq!(Pixel::rgb(255, 0, 0).hue_rotated(120.0), Pixel::rgb(0, 255, 0));
+        assert_eq!(Pixel::rgb(255, 0, 0).hue_rotated(-120.0), Pixel::rgb(0, 0, 255));
+        assert_eq!(Pixel::rgb(200, 120, 40).hue_rotated(360.0), Pixel::rgb(200, 120, 40));
+        assert_eq!(Pixel::rgb(90, 90, 90).hue_rotated(77.0), Pixel::rgb(90, 90, 90));
+    }
+
+    #[test]
+    fn test_ycbcr() {
+        let pixel = Pixel::rgb(50, 100, 150);
+        let (y, cb, cr) = pixel.to_ycbcr();
+        assert_eq!(Pixel::from_ycbcr(y, cb, cr), pixel);
+
+        let (y, cb, cr) = Pixel::rgb(128, 128, 128).to_ycbcr();
+        assert_eq!(y.round(), 128.0);
+        assert_eq!(cb.round(), 128.0);
+        assert_eq!(cr.round(), 128.0);
+    }
+
+    #[test]
+    fn test_premultiplied() {
+        let pixel = Pixel::rgba(200, 100, 50, 128);
+
+        assert_eq!(pixel.premultiplied(), Pixel::rgba(100, 50, 25, 128));
+        assert_eq!(pixel.premultiplied().unpremultiplied(), Pixel::rgba(199, 100, 50, 128));
+        assert_eq!(Pixel::rgba(9, 9, 9, 0).unpremultiplied(), Pixel::rgba(0, 0, 0, 0));
+        assert_eq!(Pixel::rgb(1, 2, 3).premultiplied(), Pixel::rgb(1, 2, 3));
+    }
+
+    #[test]
+    fn test_over() {
+        let white = Pixel::rgba(255, 255, 255, 128);
+        let black = Pixel::rgb(0, 0, 0);
+
+        assert_eq!(white.over(&black), Pixel::rgb(128, 128, 128));
+
+        let opaque = Pixel::rgb(10, 20, 30);
+        assert_eq!(opaque.over(&black), opaque);
+
+        let clear = Pixel::rgba(255, 0, 0, 0);
+        assert_eq!(clear.over(&black), black);
+    }
 }